@@ -0,0 +1,203 @@
+use std::collections::{BTreeSet, VecDeque};
+
+// Solves the "which of these goes with which of those" puzzles that keep cropping
+// up: we have a number of items, each of which must be given exactly one choice
+// from its set of candidates, and no two items may be given the same choice.
+//
+// Most of the time, plain old deduction gets there - if an item only has one
+// candidate left, no other item can have it (and if every choice must be used,
+// a choice that only one item could have must go to that item).  Those rules
+// are applied until they stop achieving anything.  If that doesn't finish the
+// job, we pick an item and try each of its candidates in turn, backtracking
+// when we hit a contradiction.  Before every step we check that it's still
+// possible for every item to get a choice at all (using Hopcroft-Karp to find a
+// maximum matching), so dead ends get spotted early.
+//
+// We stop looking once we've found two solutions - that's enough to know the
+// answer isn't unique.
+//
+// If you want to know how we got there, solve_with_deductions also returns
+// every deduction that led to the (first) solution, in order.
+
+pub enum Solution {
+    Unique(Vec<usize>),
+    Multiple(Vec<usize>, Vec<usize>),
+    Impossible,
+}
+
+#[derive(Clone)]
+pub enum Deduction {
+    // `by` could only have `choice`, so `item` can't.
+    Excluded { item: usize, choice: usize, by: usize },
+    // Nothing but `item` could have `choice`, so it must.
+    OnlyHolder { item: usize, choice: usize },
+    // Deduction ran dry, so we tried giving `item` `choice`.
+    Guessed { item: usize, choice: usize },
+}
+
+pub fn solve(candidates: Vec<BTreeSet<usize>>, choices: usize) -> Solution {
+    solve_with_deductions(candidates, choices).0
+}
+
+pub fn solve_with_deductions(candidates: Vec<BTreeSet<usize>>, choices: usize) -> (Solution, Vec<Deduction>) {
+    assert!(candidates.iter().flatten().all(|&choice| choice < choices), "Candidate out of range");
+    let mut solutions: Vec<(Vec<usize>, Vec<Deduction>)> = Vec::new();
+    search(State { candidates, choices, deductions: Vec::new() }, &mut solutions);
+    let mut solutions = solutions.into_iter();
+    match (solutions.next(), solutions.next()) {
+        (Some((first, deductions)), Some((second, _))) => (Solution::Multiple(first, second), deductions),
+        (Some((only, deductions)), None) => (Solution::Unique(only), deductions),
+        _ => (Solution::Impossible, Vec::new()),
+    }
+}
+
+fn search(mut state: State, solutions: &mut Vec<(Vec<usize>, Vec<Deduction>)>) {
+    if solutions.len() >= 2 || !state.propagate() {
+        return;
+    }
+
+    // Branch on whichever undecided item has the fewest candidates.
+    let undecided = (0..state.candidates.len()).filter(|&item| state.candidates[item].len() > 1).min_by_key(|&item| state.candidates[item].len());
+    match undecided {
+        None => {
+            let assignment = state.candidates.iter().map(|candidates| *candidates.iter().next().unwrap()).collect();
+            solutions.push((assignment, state.deductions));
+        },
+        Some(item) => {
+            for choice in state.candidates[item].clone() {
+                let mut guess = state.clone();
+                guess.candidates[item] = std::iter::once(choice).collect();
+                guess.deductions.push(Deduction::Guessed { item, choice });
+                search(guess, solutions);
+            }
+        },
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    candidates: Vec<BTreeSet<usize>>,
+    choices: usize,
+    deductions: Vec<Deduction>,
+}
+
+impl State {
+    // Returns false if we've found a contradiction.
+    fn propagate(&mut self) -> bool {
+        let items = self.candidates.len();
+        loop {
+            let mut changed = false;
+
+            for item in 0..items {
+                if self.candidates[item].len() != 1 { continue; }
+                let choice = *self.candidates[item].iter().next().unwrap();
+                for other in (0..items).filter(|&other| other != item) {
+                    if self.candidates[other].remove(&choice) {
+                        self.deductions.push(Deduction::Excluded { item: other, choice, by: item });
+                        changed = true;
+                    }
+                }
+            }
+            if self.candidates.iter().any(BTreeSet::is_empty) {
+                return false;
+            }
+
+            if items == self.choices {
+                for choice in 0..self.choices {
+                    let holders: Vec<usize> = (0..items).filter(|&item| self.candidates[item].contains(&choice)).collect();
+                    match holders.len() {
+                        0 => return false,
+                        1 if self.candidates[holders[0]].len() > 1 => {
+                            self.candidates[holders[0]] = std::iter::once(choice).collect();
+                            self.deductions.push(Deduction::OnlyHolder { item: holders[0], choice });
+                            changed = true;
+                        },
+                        _ => (),
+                    }
+                }
+            }
+
+            if !changed { break; }
+        }
+
+        Matcher::new(&self.candidates, self.choices).maximum_matching() == items
+    }
+}
+
+// Hopcroft-Karp: repeatedly find a maximal set of shortest augmenting paths
+// (a BFS to split the items into layers, then a DFS along those layers) until
+// there aren't any.
+struct Matcher<'a> {
+    candidates: &'a [BTreeSet<usize>],
+    item_match: Vec<Option<usize>>,
+    choice_match: Vec<Option<usize>>,
+    layer: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(candidates: &'a [BTreeSet<usize>], choices: usize) -> Self {
+        Self {
+            candidates,
+            item_match: vec![None; candidates.len()],
+            choice_match: vec![None; choices],
+            layer: vec![usize::MAX; candidates.len()],
+        }
+    }
+
+    fn maximum_matching(&mut self) -> usize {
+        let mut size = 0;
+        while self.build_layers() {
+            for item in 0..self.candidates.len() {
+                if self.item_match[item].is_none() && self.augment(item) {
+                    size += 1;
+                }
+            }
+        }
+        size
+    }
+
+    // Returns whether there are any augmenting paths.
+    fn build_layers(&mut self) -> bool {
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        for item in 0..self.candidates.len() {
+            if self.item_match[item].is_none() {
+                self.layer[item] = 0;
+                queue.push_back(item);
+            } else {
+                self.layer[item] = usize::MAX;
+            }
+        }
+
+        let mut found_path = false;
+        while let Some(item) = queue.pop_front() {
+            for &choice in self.candidates[item].iter() {
+                match self.choice_match[choice] {
+                    None => found_path = true,
+                    Some(next) => if self.layer[next] == usize::MAX {
+                        self.layer[next] = self.layer[item] + 1;
+                        queue.push_back(next);
+                    },
+                }
+            }
+        }
+        found_path
+    }
+
+    fn augment(&mut self, item: usize) -> bool {
+        for &choice in self.candidates[item].iter() {
+            let can_take = match self.choice_match[choice] {
+                None => true,
+                Some(next) => self.layer[next] == self.layer[item] + 1 && self.augment(next),
+            };
+            if can_take {
+                self.item_match[item] = Some(choice);
+                self.choice_match[choice] = Some(item);
+                return true;
+            }
+        }
+
+        // Dead end - don't come this way again in this phase.
+        self.layer[item] = usize::MAX;
+        false
+    }
+}
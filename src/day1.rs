@@ -1,20 +1,244 @@
-use itertools::Itertools;
+use std::collections::{BTreeSet, HashMap};
+use crate::utils;
 
-static TARGET: u64 = 2020;
+// The target defaults to 2020, but a "target=<n>" line in the input or
+// --target=<n> changes it.  A "k=<n>" line or --k=<n> additionally lists every
+// set of n expenses that adds up to the target.
+//
+// Each size of set gets its own approach: pairs by looking up each expense's
+// complement in a hash map, triples by fixing the smallest and closing in on the
+// other two from both ends of the sorted list, and anything else by meeting in
+// the middle - working out the sums of every possible first half, then looking
+// up what each possible second half needs.  Sets are of values, so listing the
+// same value twice needs it to appear twice in the input.
 
-// It's not a remotely performant solution, but it's pretty.
+static TARGET: u64 = 2020;
 
 pub fn day1(input_lines: &[String]) -> (u64, u64) {
-    let expenses: Vec<u64> = input_lines.iter().map(|line| line.parse::<u64>().expect("Failed to parse input")).collect();
-    let part1 = find_product_of_expenses_that_sum_to(&expenses, 2, TARGET).expect("Failed to solve part 1");
-    let part2 = find_product_of_expenses_that_sum_to(&expenses, 3, TARGET).expect("Failed to solve part 2");
-    (part1, part2) 
-}
-
-fn find_product_of_expenses_that_sum_to(expenses: &[u64], subset_size: usize, target: u64) -> Option<u64> {
-    expenses
-        .iter()
-        .combinations(subset_size)
-        .find(|combo| combo.iter().copied().sum::<u64>() == target)
-        .map(|combo| combo.iter().copied().product())
-}
\ No newline at end of file
+    let mut target = TARGET;
+    let mut extra_size: Option<usize> = None;
+    let mut expenses: Vec<u64> = Vec::new();
+    for line in input_lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        match line.split_once('=') {
+            Some(("target", value)) => target = value.trim().parse().expect("Invalid target in input"),
+            Some(("k", value)) => extra_size = Some(value.trim().parse().expect("Invalid k in input")),
+            _ => expenses.push(line.parse::<u64>().expect("Failed to parse input")),
+        }
+    }
+    if let Some(value) = utils::option_value("target") {
+        target = value.parse().expect("Invalid --target");
+    }
+    if let Some(value) = utils::option_value("k") {
+        extra_size = Some(value.parse().expect("Invalid --k"));
+    }
+
+    if let Some(size) = extra_size {
+        let sets = subsets_summing_to(&expenses, size, target);
+        println!("{} set(s) of {} expenses add up to {}", sets.len(), size, target);
+        for set in sets {
+            let values: Vec<String> = set.iter().map(u64::to_string).collect();
+            match product(&set) {
+                Some(product) => println!("  {} (product {})", values.join(" + "), product),
+                None => println!("  {} (product doesn't fit in 64 bits)", values.join(" + ")),
+            }
+        }
+    }
+
+    let part1 = product_of_first_set(&subsets_summing_to(&expenses, 2, target), target, "part 1");
+    let part2 = product_of_first_set(&subsets_summing_to(&expenses, 3, target), target, "part 2");
+    (part1, part2)
+}
+
+// A product too big for 64 bits is reported, and counts as 0.
+fn product_of_first_set(sets: &[Vec<u64>], target: u64, part: &str) -> u64 {
+    let set = sets.first().unwrap_or_else(|| panic!("Failed to solve {}", part));
+    if sets.len() > 1 {
+        let first: Vec<String> = set.iter().map(u64::to_string).collect();
+        println!("{} sets of {} expenses add up to {} - using the first, {} (run with --k={} to see them all)", sets.len(), set.len(), target, first.join(" + "), set.len());
+    }
+    product(set).unwrap_or_else(|| {
+        println!("The product for {} doesn't fit in 64 bits", part);
+        0
+    })
+}
+
+fn product(set: &[u64]) -> Option<u64> {
+    set.iter().try_fold(1u64, |product, &value| product.checked_mul(value))
+}
+
+// Every distinct set of `size` expenses that adds up to the target, each in
+// increasing order, in increasing order.
+fn subsets_summing_to(expenses: &[u64], size: usize, target: u64) -> Vec<Vec<u64>> {
+    let mut sorted = expenses.to_vec();
+    sorted.sort_unstable();
+    match size {
+        2 => pairs_summing_to(&sorted, target),
+        3 => triples_summing_to(&sorted, target),
+        _ => meet_in_the_middle(&sorted, size, target),
+    }
+}
+
+fn pairs_summing_to(sorted: &[u64], target: u64) -> Vec<Vec<u64>> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for &expense in sorted {
+        *counts.entry(expense).or_insert(0) += 1;
+    }
+    let mut pairs = Vec::new();
+    for (index, &expense) in sorted.iter().enumerate().take_while(|&(_, &expense)| expense.saturating_mul(2) <= target) {
+        if index > 0 && sorted[index - 1] == expense { continue; }
+        let complement = target - expense;
+        if (complement > expense && counts.contains_key(&complement)) || (complement == expense && counts[&expense] > 1) {
+            pairs.push(vec![expense, complement]);
+        }
+    }
+    pairs
+}
+
+fn triples_summing_to(sorted: &[u64], target: u64) -> Vec<Vec<u64>> {
+    let mut triples = Vec::new();
+    for first in 0..sorted.len() {
+        if first > 0 && sorted[first] == sorted[first - 1] { continue; }
+        let remaining = match target.checked_sub(sorted[first]) {
+            Some(remaining) => remaining,
+            None => break,
+        };
+        let (mut low, mut high) = (first + 1, sorted.len().saturating_sub(1));
+        while low < high {
+            let sum = sorted[low].saturating_add(sorted[high]);
+            if sum < remaining {
+                low += 1;
+            } else if sum > remaining {
+                high -= 1;
+            } else {
+                triples.push(vec![sorted[first], sorted[low], sorted[high]]);
+                // Skip past repeats, so we don't find the same triple again.
+                while low < high && sorted[low] == sorted[low + 1] { low += 1; }
+                while low < high && sorted[high] == sorted[high - 1] { high -= 1; }
+                low += 1;
+                high -= 1;
+            }
+        }
+    }
+    triples
+}
+
+// Any set of indices can be split into its smallest size/2 and the rest, so pair
+// up every possible first half with every possible second half that needs its
+// sum and starts after it finishes.  A first half still has to leave room for
+// the second half's values after it, and a second half has to leave room for
+// the smallest possible first half, which cuts down what gets enumerated a lot.
+fn meet_in_the_middle(sorted: &[u64], size: usize, target: u64) -> Vec<Vec<u64>> {
+    if size > sorted.len() {
+        return Vec::new();
+    }
+    let first_size = size / 2;
+    let second_size = size - first_size;
+    let mut first_halves: HashMap<u64, Vec<Vec<usize>>> = HashMap::new();
+    for_each_combination(sorted, first_size, 0, second_size, target, &mut |indices, sum| {
+        first_halves.entry(sum).or_default().push(indices.to_vec());
+    });
+    // Sorted by where they finish, so we can stop at the first one that runs
+    // into the second half.
+    for halves in first_halves.values_mut() {
+        halves.sort_by_key(|half| half.last().copied());
+    }
+
+    let max_sum = match target.checked_sub(sorted[..first_size].iter().sum()) {
+        Some(max_sum) => max_sum,
+        None => return Vec::new(),
+    };
+    let mut sets: BTreeSet<Vec<u64>> = BTreeSet::new();
+    for_each_combination(sorted, second_size, first_size, 0, max_sum, &mut |second_half, sum| {
+        let finishes_in_time = |first_half: &&Vec<usize>| match (first_half.last(), second_half.first()) {
+            (Some(last), Some(first)) => last < first,
+            _ => true,
+        };
+        for first_half in first_halves.get(&(target - sum)).into_iter().flatten().take_while(finishes_in_time) {
+            sets.insert(first_half.iter().chain(second_half).map(|&index| sorted[index]).collect());
+        }
+    });
+    sets.into_iter().collect()
+}
+
+// Call `action` with every set of `size` indices (in increasing order, from
+// `first` on) into the sorted values, and the sum of those values, skipping any
+// that couldn't be followed by `reserve` more values without adding up to more
+// than `max_sum`.
+fn for_each_combination(sorted: &[u64], size: usize, first: usize, reserve: usize, max_sum: u64, action: &mut impl FnMut(&[usize], u64)) {
+    struct Search<'a, F> {
+        sorted: &'a [u64],
+        // prefix_sums[i] is the sum of the first i values.
+        prefix_sums: Vec<u64>,
+        size: usize,
+        reserve: usize,
+        max_sum: u64,
+        indices: Vec<usize>,
+        action: &'a mut F,
+    }
+
+    impl<F: FnMut(&[usize], u64)> Search<'_, F> {
+        fn extend(&mut self, start: usize, sum: u64) {
+            if self.indices.len() == self.size {
+                (self.action)(&self.indices, sum);
+                return;
+            }
+            let needed = self.size - self.indices.len() + self.reserve;
+            for index in start..=self.sorted.len().saturating_sub(needed) {
+                // The smallest this could possibly end up as is if it carries on
+                // with the values straight after this one.  The values only get
+                // bigger from here, so if that's too much, so is everything else.
+                let lowest = sum.saturating_add(self.prefix_sums[index + needed] - self.prefix_sums[index]);
+                if lowest > self.max_sum { break; }
+                self.indices.push(index);
+                self.extend(index + 1, sum.saturating_add(self.sorted[index]));
+                self.indices.pop();
+            }
+        }
+    }
+
+    if first + size + reserve > sorted.len() {
+        return;
+    }
+    let mut prefix_sums = vec![0u64];
+    for &value in sorted {
+        prefix_sums.push(prefix_sums[prefix_sums.len() - 1].saturating_add(value));
+    }
+    let mut search = Search { sorted, prefix_sums, size, reserve, max_sum, indices: Vec::with_capacity(size), action };
+    search.extend(first, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_expenses(count: usize, low: u64, high: u64, seed: u64) -> Vec<u64> {
+        let mut random = utils::random_numbers(seed);
+        (0..count).map(|_| low + random(high - low + 1)).collect()
+    }
+
+    // Lots of repeated values, checked against trying every set of indices.
+    #[test]
+    fn meet_in_the_middle_matches_every_combination() {
+        let mut expenses = random_expenses(30, 1, 60, 2020);
+        expenses.sort_unstable();
+        for size in 1..=5 {
+            let mut every_set: BTreeSet<Vec<u64>> = BTreeSet::new();
+            for_each_combination(&expenses, size, 0, 0, u64::MAX, &mut |indices, sum| {
+                if sum == 100 { every_set.insert(indices.iter().map(|&index| expenses[index]).collect()); }
+            });
+            assert_eq!(meet_in_the_middle(&expenses, size, 100), every_set.into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    // Far too many expenses to try every set of six, with a few sets that add up
+    // hidden among them.
+    #[test]
+    fn large_input() {
+        let mut expenses = random_expenses(2000, 300, 100_000, 2021);
+        expenses.extend(&[301, 302, 303, 304, 305, 505, 310, 320, 330, 340, 350, 370]);
+        let sets = subsets_summing_to(&expenses, 6, 2020);
+        assert!(sets.contains(&vec![301, 302, 303, 304, 305, 505]));
+        assert!(sets.contains(&vec![310, 320, 330, 340, 350, 370]));
+        assert!(sets.iter().all(|set| set.len() == 6 && set.iter().sum::<u64>() == 2020));
+    }
+}
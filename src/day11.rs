@@ -115,7 +115,7 @@ fn apply_round(grid: &Grid, ruleset: Rulesets) -> Grid {
     for (row, col) in (0..ROWS).cartesian_product(0..COLS) {
         new_grid[row][col] = match grid[row][col] {
             States::Floor => States::Floor,
-            States::Empty => if count_adjacent_occupied_seats(&grid, row, col, ruleset) == 0 { States::Occupied } else { States::Empty },
+            States::Empty => if count_adjacent_occupied_seats(grid, row, col, ruleset) == 0 { States::Occupied } else { States::Empty },
             States::Occupied if ruleset == Rulesets::Part1 => if count_adjacent_occupied_seats(grid, row, col, ruleset) >= 4 { States::Empty } else { States::Occupied },
             States::Occupied => if count_adjacent_occupied_seats(grid, row, col, ruleset) >= 5 { States::Empty } else { States::Occupied },
         }
@@ -1,8 +1,60 @@
+use std::io::Write;
+use crate::utils;
+
+// Every ship follows the same instructions, but each kind of ship interprets
+// them in its own way.  Part 1 uses the "heading" ship and part 2 the "waypoint"
+// one; run with --ships=<name>[,<name>...] to sail any of the ships in
+// SHIP_MODELS as well, and see where each of them was after every instruction.
+//
+// Run with --plot to draw the ships' routes (and where any waypoints went) as
+// ASCII art, or --plot=<file> to save them as an SVG.
+
+const PLOT_WIDTH: usize = 78;
+const PLOT_HEIGHT: usize = 39;
+const SVG_SIZE: f64 = 800.0;
+
+struct ShipModel {
+    name: &'static str,
+    description: &'static str,
+    build: fn() -> Box<dyn Ship>,
+}
+
+const SHIP_MODELS: &[ShipModel] = &[
+    ShipModel { name: "heading", description: "N/S/E/W move the ship, L/R turn it, F moves it forwards", build: || Box::new(Part1Ship::new()) },
+    ShipModel { name: "waypoint", description: "N/S/E/W move the waypoint, L/R rotate it around the ship, F moves the ship towards it", build: || Box::new(Part2Ship::new()) },
+    ShipModel { name: "relative-waypoint", description: "like waypoint, but N/S/E/W move the waypoint ahead, astern, to starboard and to port", build: || Box::new(RelativeWaypointShip::new()) },
+    ShipModel { name: "drift", description: "like heading, but a current (--current=<east>,<south>, default 0,1) moves the ship after every instruction", build: || Box::new(DriftingShip::new()) },
+];
+
 pub fn day12(input_lines: &[String]) -> (u64, u64) {
-    let mut ships: Vec<Box<dyn Ship>> = vec![Box::new(Part1Ship::new()), Box::new(Part2Ship::new())];
-    input_lines.iter().map(|line| Instruction::parse(line)).for_each(|instruction| { ships.iter_mut().for_each(|ship| ship.apply_instruction(&instruction)) });
-    let part1 = ships[0].manhattan_distance() as u64;
-    let part2 = ships[1].manhattan_distance() as u64;
+    let mut ship_names = vec!["heading", "waypoint"];
+    let chosen_ships: Vec<String> = utils::option_value("ships").map_or(Vec::new(), |ships| ships.split(',').map(str::to_string).collect());
+    for name in &chosen_ships {
+        if !ship_names.contains(&name.as_str()) {
+            ship_names.push(SHIP_MODELS.iter().find(|model| model.name == name).map(|model| model.name).unwrap_or_else(|| {
+                let known: Vec<&str> = SHIP_MODELS.iter().map(|model| model.name).collect();
+                panic!("Unknown ship {} - try one of {}", name, known.join(", "))
+            }));
+        }
+    }
+
+    let mut voyages: Vec<Voyage> = ship_names.iter().map(|&name| Voyage::new(name)).collect();
+    input_lines.iter().map(|line| Instruction::parse(line)).for_each(|instruction| { voyages.iter_mut().for_each(|voyage| voyage.apply_instruction(&instruction)) });
+    let part1 = voyages[0].ship.manhattan_distance() as u64;
+    let part2 = voyages[1].ship.manhattan_distance() as u64;
+
+    for voyage in voyages.iter().filter(|voyage| chosen_ships.iter().any(|name| name == voyage.model.name)) {
+        voyage.report(input_lines);
+    }
+
+    if utils::option_set("plot") {
+        let plot = Plot::new(&voyages);
+        match utils::option_value("plot") {
+            Some(file) => std::fs::write(&file, plot.to_svg()).expect("Can't write plot file"),
+            None => print!("{}", plot),
+        }
+    }
+
     (part1,part2)
 }
 
@@ -11,6 +63,22 @@ enum Turns {
     Left,
     Right,
     Around,
+    None,
+}
+
+impl Turns {
+    // Angles are clockwise for R, anticlockwise for L.
+    fn from_angle(clockwise_degrees: i64) -> Option<Self> {
+        if clockwise_degrees % 90 != 0 {
+            return None;
+        }
+        match (clockwise_degrees / 90).rem_euclid(4) {
+            0 => Some(Self::None),
+            1 => Some(Self::Right),
+            2 => Some(Self::Around),
+            _ => Some(Self::Left),
+        }
+    }
 }
 
 #[derive(Clone,Copy)]
@@ -30,21 +98,25 @@ impl std::ops::Add<Turns> for Directions {
                 Turns::Left => Self::West,
                 Turns::Right => Self::East,
                 Turns::Around => Self::South,
+                Turns::None => Self::North,
             },
             Self::South => match rhs {
                 Turns::Left => Self::East,
                 Turns::Right => Self::West,
                 Turns::Around => Self::North,
+                Turns::None => Self::South,
             },
             Self::East => match rhs {
                 Turns::Left => Self::North,
                 Turns::Right => Self::South,
                 Turns::Around => Self::West,
+                Turns::None => Self::East,
             },
             Self::West => match rhs {
                 Turns::Left => Self::South,
                 Turns::Right => Self::North,
                 Turns::Around => Self::East,
+                Turns::None => Self::West,
             },
         }
     }
@@ -82,6 +154,8 @@ struct Position {
 }
 
 impl Position {
+    const ORIGIN: Position = Position { north_south: 0, east_west: 0 };
+
     fn apply_movement(&mut self, movement: &Movement) {
         match movement.direction {
             Directions::North => self.north_south -= movement.distance,
@@ -102,12 +176,25 @@ impl Position {
                 north_south: self.east_west,
                 east_west: -self.north_south,
             },
+            Turns::None => (),
         }
     }
 
     fn manhattan_distance(&self) -> i64 {
         self.north_south.abs() + self.east_west.abs()
     }
+
+    fn euclidean_distance(&self) -> f64 {
+        (self.north_south as f64).hypot(self.east_west as f64)
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let north_south = if self.north_south > 0 { 'S' } else { 'N' };
+        let east_west = if self.east_west < 0 { 'W' } else { 'E' };
+        write!(f, "{}{} {}{}", self.east_west.abs(), east_west, self.north_south.abs(), north_south)
+    }
 }
 
 impl std::ops::Add for Position {
@@ -161,24 +248,39 @@ enum Instruction {
 impl Instruction {
     fn parse (input: &str) -> Self {
         let amount = input[1..].parse::<i64>().expect("Invalid input");
+        let turn = |clockwise_degrees: i64| {
+            let turn = Turns::from_angle(clockwise_degrees);
+            Self::Turn(turn.unwrap_or_else(|| panic!("Invalid input: can only turn by multiples of 90 degrees, not {}", input)))
+        };
         match input.chars().next().expect("Invalid input") {
             'N' => Self::Move(Movement { direction: Directions::North, distance: amount }),
             'S' => Self::Move(Movement { direction: Directions::South, distance: amount }),
             'E' => Self::Move(Movement { direction: Directions::East, distance: amount }),
             'W' => Self::Move(Movement { direction: Directions::West, distance: amount }),
             'F' => Self::Forwards(amount),
-            'L' if amount == 90 => Self::Turn(Turns::Left),
-            'L' if amount == 270 => Self::Turn(Turns::Right),
-            'R' if amount == 90 => Self::Turn(Turns::Right),
-            'R' if amount == 270 => Self::Turn(Turns::Left),
-            _ => Self::Turn(Turns::Around),
+            'L' => turn(-amount),
+            'R' => turn(amount),
+            _ => panic!("Invalid input: unknown instruction {}", input),
         }
     }
 }
 
 trait Ship {
     fn apply_instruction(&mut self, instruction: &Instruction);
-    fn manhattan_distance(&self) -> i64;
+    fn position(&self) -> Position;
+
+    // Where the ship's waypoint is, relative to the ship - if it has one.
+    fn waypoint(&self) -> Option<Position> {
+        None
+    }
+
+    fn manhattan_distance(&self) -> i64 {
+        self.position().manhattan_distance()
+    }
+
+    fn euclidean_distance(&self) -> f64 {
+        self.position().euclidean_distance()
+    }
 }
 
 struct Part1Ship {
@@ -189,10 +291,7 @@ struct Part1Ship {
 impl Part1Ship {
     fn new() -> Self {
         Self {
-            position: Position {
-                east_west: 0,
-                north_south: 0,
-            },
+            position: Position::ORIGIN,
             heading: Directions::East,
         }
     }
@@ -207,8 +306,8 @@ impl Ship for Part1Ship {
         }
     }
 
-    fn manhattan_distance(&self) -> i64 {
-        self.position.manhattan_distance()
+    fn position(&self) -> Position {
+        self.position
     }
 }
 
@@ -220,10 +319,7 @@ struct Part2Ship {
 impl Part2Ship {
     fn new() -> Self {
         Self {
-            position: Position {
-                east_west: 0,
-                north_south: 0,
-            },
+            position: Position::ORIGIN,
             waypoint: Position {
                 east_west: 10,
                 north_south: -1,
@@ -241,7 +337,232 @@ impl Ship for Part2Ship {
         }
     }
 
-    fn manhattan_distance(&self) -> i64 {
-        self.position.manhattan_distance()
+    fn position(&self) -> Position {
+        self.position
+    }
+
+    fn waypoint(&self) -> Option<Position> {
+        Some(self.waypoint)
+    }
+}
+
+// The waypoint is kept relative to the way the ship's facing, as how far ahead
+// (north_south) and to starboard (east_west) it is, as though the ship were
+// facing north - so turning the ship takes the waypoint with it.
+struct RelativeWaypointShip {
+    position: Position,
+    heading: Directions,
+    waypoint: Position,
+}
+
+impl RelativeWaypointShip {
+    fn new() -> Self {
+        Self {
+            position: Position::ORIGIN,
+            heading: Directions::East,
+            waypoint: Position {
+                east_west: -1,
+                north_south: -10,
+            },
+        }
+    }
+
+    // The waypoint in the usual north/east terms.
+    fn absolute_waypoint(&self) -> Position {
+        let mut waypoint = self.waypoint;
+        waypoint.rotate(&match self.heading {
+            Directions::North => Turns::None,
+            Directions::East => Turns::Right,
+            Directions::South => Turns::Around,
+            Directions::West => Turns::Left,
+        });
+        waypoint
+    }
+}
+
+impl Ship for RelativeWaypointShip {
+    fn apply_instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Move(movement) => self.waypoint.apply_movement(movement),
+            Instruction::Forwards(distance) => self.position += self.absolute_waypoint() * distance,
+            Instruction::Turn(turn) => self.heading += turn,
+        }
+    }
+
+    fn position(&self) -> Position {
+        self.position
+    }
+
+    fn waypoint(&self) -> Option<Position> {
+        Some(self.absolute_waypoint())
     }
-}
\ No newline at end of file
+}
+
+struct DriftingShip {
+    ship: Part1Ship,
+    current: Position,
+}
+
+impl DriftingShip {
+    fn new() -> Self {
+        let current = utils::option_value("current").map_or(Position { east_west: 0, north_south: 1 }, |current| {
+            let parts: Vec<i64> = current.split(',').map(|part| part.parse().expect("Invalid --current")).collect();
+            match parts[..] {
+                [east_west, north_south] => Position { east_west, north_south },
+                _ => panic!("Invalid --current: should be <east>,<south>"),
+            }
+        });
+        Self { ship: Part1Ship::new(), current }
+    }
+}
+
+impl Ship for DriftingShip {
+    fn apply_instruction(&mut self, instruction: &Instruction) {
+        self.ship.apply_instruction(instruction);
+        self.ship.position += self.current;
+    }
+
+    fn position(&self) -> Position {
+        self.ship.position()
+    }
+}
+
+// A ship, and everywhere it (and its waypoint, if any) has been - starting
+// where it started, then after every instruction.
+struct Voyage {
+    model: &'static ShipModel,
+    ship: Box<dyn Ship>,
+    route: Vec<Position>,
+    waypoint_route: Vec<Position>,
+}
+
+impl Voyage {
+    fn new(name: &str) -> Self {
+        let model = SHIP_MODELS.iter().find(|model| model.name == name).unwrap();
+        let mut voyage = Self { model, ship: (model.build)(), route: Vec::new(), waypoint_route: Vec::new() };
+        voyage.record();
+        voyage
+    }
+
+    fn apply_instruction(&mut self, instruction: &Instruction) {
+        self.ship.apply_instruction(instruction);
+        self.record();
+    }
+
+    fn record(&mut self) {
+        let position = self.ship.position();
+        self.route.push(position);
+        if let Some(waypoint) = self.ship.waypoint() {
+            self.waypoint_route.push(position + waypoint);
+        }
+    }
+
+    fn report(&self, instructions: &[String]) {
+        println!("{} ship: {}", self.model.name, self.model.description);
+        for (index, (instruction, position)) in instructions.iter().zip(self.route.iter().skip(1)).enumerate() {
+            match self.waypoint_route.get(index + 1) {
+                Some(waypoint) => println!("  {:>5} -> {} (waypoint {})", instruction, position, waypoint),
+                None => println!("  {:>5} -> {}", instruction, position),
+            }
+        }
+        println!("  Manhattan distance {}, Euclidean distance {:.2}", self.ship.manhattan_distance(), self.ship.euclidean_distance());
+    }
+}
+
+// The routes to draw, each with the character and colour to draw it in, scaled
+// so they all fit.  Later routes are drawn on top of earlier ones.
+struct Plot<'a> {
+    routes: Vec<(&'a [Position], char, &'static str)>,
+    legend: Vec<(char, String)>,
+    min: Position,
+    max: Position,
+}
+
+impl<'a> Plot<'a> {
+    fn new(voyages: &'a [Voyage]) -> Self {
+        let mut routes = Vec::new();
+        let mut legend = Vec::new();
+        for voyage in voyages {
+            if !voyage.waypoint_route.is_empty() {
+                routes.push((&voyage.waypoint_route[..], '.', "#bbbbbb"));
+            }
+        }
+        let colours = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd"];
+        for (index, voyage) in voyages.iter().enumerate() {
+            let symbol = std::char::from_digit((index + 1) as u32 % 10, 10).unwrap();
+            routes.push((&voyage.route[..], symbol, colours[index % colours.len()]));
+            legend.push((symbol, format!("{} ship", voyage.model.name)));
+        }
+        if routes.len() > voyages.len() {
+            legend.push(('.', "waypoints".to_string()));
+        }
+        legend.push(('S', "start".to_string()));
+
+        let all_positions = || routes.iter().flat_map(|(route, _, _)| route.iter());
+        let min = Position {
+            north_south: all_positions().map(|position| position.north_south).min().unwrap_or(0),
+            east_west: all_positions().map(|position| position.east_west).min().unwrap_or(0),
+        };
+        let max = Position {
+            north_south: all_positions().map(|position| position.north_south).max().unwrap_or(0),
+            east_west: all_positions().map(|position| position.east_west).max().unwrap_or(0),
+        };
+        Self { routes, legend, min, max }
+    }
+
+    // Where a position ends up, with the plot running from 0 to width x height.
+    fn scale(&self, position: &Position, width: f64, height: f64) -> (f64, f64) {
+        let span = |min: i64, max: i64| (max - min).max(1) as f64;
+        ((position.east_west - self.min.east_west) as f64 / span(self.min.east_west, self.max.east_west) * width,
+         (position.north_south - self.min.north_south) as f64 / span(self.min.north_south, self.max.north_south) * height)
+    }
+
+    fn to_svg(&self) -> String {
+        let margin = SVG_SIZE / 40.0;
+        let mut output: Vec<u8> = Vec::new();
+        writeln!(output, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+                 SVG_SIZE, SVG_SIZE, -margin, -margin, SVG_SIZE + 2.0 * margin, SVG_SIZE + 2.0 * margin).unwrap();
+        writeln!(output, r#"<rect x="{}" y="{}" width="100%" height="100%" fill="white"/>"#, -margin, -margin).unwrap();
+        for (route, _, colour) in &self.routes {
+            let points: Vec<String> = route.iter().map(|position| {
+                let (x, y) = self.scale(position, SVG_SIZE, SVG_SIZE);
+                format!("{:.1},{:.1}", x, y)
+            }).collect();
+            writeln!(output, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1.5"/>"#, points.join(" "), colour).unwrap();
+        }
+        let (x, y) = self.scale(&Position::ORIGIN, SVG_SIZE, SVG_SIZE);
+        writeln!(output, r#"<circle cx="{:.1}" cy="{:.1}" r="4" fill="black"/>"#, x, y).unwrap();
+        writeln!(output, "</svg>").unwrap();
+        String::from_utf8(output).unwrap()
+    }
+}
+
+impl std::fmt::Display for Plot<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut grid = vec![vec![' '; PLOT_WIDTH]; PLOT_HEIGHT];
+        let cell = |position: &Position| {
+            let (x, y) = self.scale(position, (PLOT_WIDTH - 1) as f64, (PLOT_HEIGHT - 1) as f64);
+            (x.round() as usize, y.round() as usize)
+        };
+        for (route, symbol, _) in &self.routes {
+            for leg in route.windows(2) {
+                // Step along each leg finely enough not to skip any cells.
+                let (start, end) = (cell(&leg[0]), cell(&leg[1]));
+                let steps = (end.0 as i64 - start.0 as i64).abs().max((end.1 as i64 - start.1 as i64).abs()).max(1);
+                for step in 0..=steps {
+                    let along = |from: usize, to: usize| (from as f64 + (to as f64 - from as f64) * step as f64 / steps as f64).round() as usize;
+                    grid[along(start.1, end.1)][along(start.0, end.0)] = *symbol;
+                }
+            }
+        }
+        let (x, y) = cell(&Position::ORIGIN);
+        grid[y][x] = 'S';
+
+        let legend: Vec<String> = self.legend.iter().map(|(symbol, label)| format!("{} {}", symbol, label)).collect();
+        writeln!(f, "{}", legend.join("   "))?;
+        for row in grid {
+            writeln!(f, "{}", row.into_iter().collect::<String>().trim_end())?;
+        }
+        Ok(())
+    }
+}
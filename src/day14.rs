@@ -1,68 +1,172 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use crate::utils;
+
+// Run with --trace to see every mask change and every write as it happens -
+// what value gets written in part 1, and which addresses get written in part 2 -
+// followed by the final contents of both memories.
+
+const ADDRESS_BITS: usize = 36;
+const MAX_TRACED_ADDRESSES: usize = 16;
 
 pub fn day14(input_lines: &[String]) -> (u64, u64) {
+    let trace = utils::option_set("trace");
     let mut mem1 = Part1Memory::new();
     let mut mem2 = Part2Memory::new();
     for line in input_lines {
         if line.starts_with("mask") {
             let mask = &line[7..];
+            if trace { println!("mask = {}", mask); }
             mem1.set_mask(mask);
             mem2.set_mask(mask);
         } else {
             let addr = line[4..line.find(']').expect("Invalid input")].parse::<u64>().expect("Invalid input");
             let value = line[line.find('=').expect("Invalid input") + 2..].parse::<u64>().expect("Invalid input");
+            if trace {
+                println!("mem[{}] = {}", addr, value);
+                println!("  part 1: {}", mem1.describe_write(addr, value));
+                println!("  part 2: {}", mem2.describe_write(addr));
+            }
             mem1.set_memory(addr, value);
             mem2.set_memory(addr, value);
         }
     }
+    if trace {
+        println!("Part 1 memory:");
+        mem1.dump();
+        println!("Part 2 memory:");
+        mem2.dump();
+    }
     let part1 = mem1.values_sum();
     let part2 = mem2.values_sum();
     (part1,part2)
 }
 
+fn binary(value: u64) -> String {
+    format!("{:0width$b}", value, width = ADDRESS_BITS)
+}
+
+// Rather than writing every address a floating mask could produce (2^k of them
+// for k floating bits), part 2 remembers each write as a pattern of addresses,
+// exactly as it was written.  What a write adds to the sum is its value times
+// the number of its addresses that no later write covers, which is worked out
+// by going through the writes backwards and keeping track of everything
+// written so far by inclusion-exclusion: a count for each pattern, where
+// adding a pattern also subtracts its intersection with everything already
+// counted.  Intersecting two patterns just means fixing the bits that either
+// of them fixes, and identical patterns share a count, so this stays small.
 struct Part2Memory {
-    floating_bits: Vec<u64>,
+    floating_bits: u64,
     ones_mask: u64,
-    memory: HashMap<u64, u64>,
+    writes: Vec<(AddressPattern, u64)>,
 }
 
 impl Part2Memory {
     fn new() -> Self {
         Self {
-            floating_bits: Vec::new(),
+            floating_bits: 0,
             ones_mask: 0,
-            memory: HashMap::new(),
+            writes: Vec::new(),
         }
-    }    
+    }
 
     fn set_mask(&mut self, mask: &str) {
-        self.floating_bits.clear();
+        self.floating_bits = u64::from_str_radix(&mask.replace('1', "0").replace('X', "1"), 2).expect("Invalid mask");
         self.ones_mask = u64::from_str_radix(&mask.replace('X', "0"), 2).expect("Invalid mask");
-        for (bit, c) in mask.chars().rev().enumerate() {
-            if c == 'X' {
-                self.floating_bits.push(1u64 << bit);
-            }
-        }
     }
 
+    fn pattern_for(&self, address: u64) -> AddressPattern {
+        AddressPattern { floating: self.floating_bits, fixed: (address | self.ones_mask) & !self.floating_bits }
+    }
+
+    // Writing zero still overwrites whatever was there before, so zeroes have
+    // to be kept as well.
     fn set_memory(&mut self, address: u64, value: u64) {
-        self.recursive_set_memory(address | self.ones_mask, value, 0);
+        let pattern = self.pattern_for(address);
+        self.writes.push((pattern, value));
     }
 
-    fn recursive_set_memory(&mut self, address: u64, value: u64, bit_index: usize) {
-        // Called twice for every entry in floating_bits, once with each bit value.
-        if bit_index < self.floating_bits.len() {
-            // There's another bit to toggle.
-            self.recursive_set_memory(address | self.floating_bits[bit_index], value, bit_index + 1);
-            self.recursive_set_memory(address & !self.floating_bits[bit_index], value, bit_index + 1);
-        } else {
-            // We're at the bottom of the recursion!
-            self.memory.insert(address, value);
+    fn describe_write(&self, address: u64) -> String {
+        let pattern = self.pattern_for(address);
+        let addresses: Vec<String> = pattern.addresses().take(MAX_TRACED_ADDRESSES).map(|address| address.to_string()).collect();
+        let more = if pattern.size() > MAX_TRACED_ADDRESSES as u128 { format!(" and {} more", pattern.size() - MAX_TRACED_ADDRESSES as u128) } else { String::new() };
+        format!("{} = {}{}", pattern, addresses.join(", "), more)
+    }
+
+    // How many of each write's addresses still hold its value at the end, in
+    // the same order as the writes.
+    fn surviving_sizes(&self) -> Vec<u128> {
+        let mut covered: HashMap<AddressPattern, i128> = HashMap::new();
+        let mut sizes: Vec<u128> = self.writes.iter().rev().map(|(pattern, _)| {
+            let intersections: Vec<(AddressPattern, i128)> = covered.iter().filter_map(|(other, &count)| pattern.intersection(other).map(|both| (both, count))).collect();
+            let already_covered: i128 = intersections.iter().map(|(both, count)| count * both.size() as i128).sum();
+            for (both, count) in intersections {
+                *covered.entry(both).or_insert(0) -= count;
+            }
+            *covered.entry(*pattern).or_insert(0) += 1;
+            covered.retain(|_, count| *count != 0);
+            (pattern.size() as i128 - already_covered) as u128
+        }).collect();
+        sizes.reverse();
+        sizes
+    }
+
+    // Every write that's still in memory, as patterns, how many of its
+    // addresses still hold it, and what's stored there.
+    fn dump(&self) {
+        for ((pattern, value), size) in self.writes.iter().zip(self.surviving_sizes()).filter(|&(_, size)| size > 0) {
+            println!("  {} ({} of {} addresses) = {:#x} = {}", pattern, size, pattern.size(), value, binary(*value));
         }
     }
 
     fn values_sum(&self) -> u64 {
-        self.memory.values().sum()
+        let sum: u128 = self.writes.iter().zip(self.surviving_sizes()).map(|((_, value), size)| size * *value as u128).sum();
+        u64::try_from(sum).expect("Sum of memory doesn't fit in 64 bits")
+    }
+}
+
+// The set of addresses that match `fixed` everywhere except the floating bits,
+// which can be anything.  `fixed` is always zero where the bits are floating.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct AddressPattern {
+    floating: u64,
+    fixed: u64,
+}
+
+impl AddressPattern {
+    fn size(&self) -> u128 {
+        1u128 << self.floating.count_ones()
+    }
+
+    // The addresses in both patterns, if there are any: they have to agree
+    // wherever both are fixed, and then only bits floating in both still float.
+    fn intersection(&self, other: &AddressPattern) -> Option<AddressPattern> {
+        if (self.fixed ^ other.fixed) & !self.floating & !other.floating != 0 {
+            return None;
+        }
+        Some(AddressPattern { floating: self.floating & other.floating, fixed: self.fixed | other.fixed })
+    }
+
+    // Every address in the pattern, in increasing order: count through every
+    // combination of the floating bits, by treating them as a number on their own.
+    fn addresses(&self) -> impl Iterator<Item = u64> + '_ {
+        let mut floating_value = Some(0u64);
+        std::iter::from_fn(move || {
+            let address = self.fixed | floating_value?;
+            // Set every non-floating bit so adding 1 carries straight past them.
+            let next = ((floating_value? | !self.floating).wrapping_add(1)) & self.floating;
+            floating_value = if next == 0 { None } else { Some(next) };
+            Some(address)
+        })
+    }
+}
+
+impl std::fmt::Display for AddressPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pattern: String = (0..ADDRESS_BITS).rev().map(|bit| {
+            if self.floating & (1 << bit) != 0 { 'X' } else if self.fixed & (1 << bit) != 0 { '1' } else { '0' }
+        }).collect();
+        write!(f, "{}", pattern)
     }
 }
 
@@ -86,12 +190,59 @@ impl Part1Memory {
         self.ones_mask = u64::from_str_radix(&mask.replace('X', "0"), 2).expect("Invalid mask");
     }
 
+    fn masked(&self, value: u64) -> u64 {
+        (value | self.ones_mask) & self.zeroes_mask
+    }
+
     fn set_memory(&mut self, address: u64, value: u64) {
-        let masked_value = (value | self.ones_mask) & self.zeroes_mask;
-        self.memory.insert(address, masked_value);
+        self.memory.insert(address, self.masked(value));
+    }
+
+    fn describe_write(&self, address: u64, value: u64) -> String {
+        let masked_value = self.masked(value);
+        format!("mem[{}] = {} ({})", address, binary(masked_value), masked_value)
+    }
+
+    fn dump(&self) {
+        let mut addresses: Vec<&u64> = self.memory.keys().collect();
+        addresses.sort();
+        for address in addresses {
+            let value = self.memory[address];
+            println!("  {:#011x} = {:#x} = {}", address, value, binary(value));
+        }
     }
 
     fn values_sum(&self) -> u64 {
         self.memory.values().sum()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Masks with lots of floating bits in the same few places, so almost every
+    // write overlaps lots of earlier ones, checked against writing out every
+    // address.
+    #[test]
+    fn dense_overlapping_floating_masks() {
+        let mut random = utils::random_numbers(2020);
+        let mut memory = Part2Memory::new();
+        let mut every_address: HashMap<u64, u64> = HashMap::new();
+        for _ in 0..60 {
+            let mask: String = (0..ADDRESS_BITS).map(|bit| match (bit >= ADDRESS_BITS - 16, random(3)) {
+                (false, _) => '0',
+                (true, 0) => '1',
+                (true, _) => 'X',
+            }).collect();
+            memory.set_mask(&mask);
+            for _ in 0..4 {
+                let (address, value) = (random(1 << 16), random(4));
+                memory.set_memory(address, value);
+                for address in memory.pattern_for(address).addresses() {
+                    every_address.insert(address, value);
+                }
+            }
+        }
+        assert_eq!(memory.values_sum(), every_address.values().sum::<u64>());
+    }
+}
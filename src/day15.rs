@@ -1,22 +1,96 @@
-const MAX_TURN: usize = 30000000;
+use std::collections::HashMap;
+use crate::utils;
+
+// Run with --turns=<n>[,<n>...] to see the numbers spoken on other turns too
+// (they're all found in the same game).
+
+const PART1_TURN: usize = 2020;
+const PART2_TURN: usize = 30000000;
 
 pub fn day15(input_lines: &[String]) -> (u64, u64) {
-    let starting_nums: Vec<usize> = input_lines[0].split(',').map(|num| num.parse::<usize>().expect("Invald input")).collect();
-    let mut last_seen = vec![0; MAX_TURN].into_boxed_slice();
-    for (index, num) in starting_nums[0..starting_nums.len()-1].iter().enumerate() {
-        last_seen[*num] = index + 1;
+    let starting_nums: Vec<u32> = input_lines[0].split(',').map(|num| num.parse::<u32>().expect("Invalid input")).collect();
+    let extra_turns: Vec<usize> = utils::option_value("turns").map_or(Vec::new(), |turns| {
+        turns.split(',').map(|turn| turn.parse().expect("Invalid --turns")).collect()
+    });
+    let mut turns = vec![PART1_TURN, PART2_TURN];
+    turns.extend(extra_turns.iter().copied());
+
+    let numbers = MemoryGame::numbers_on_turns(&starting_nums, &turns);
+    for (turn, number) in extra_turns.iter().zip(numbers.iter().skip(2)) {
+        println!("Turn {}: {}", turn, number);
+    }
+    (numbers[0] as u64, numbers[1] as u64)
+}
+
+// Keeps track of when each number was last spoken.  Numbers can't be bigger than
+// the number of turns so far, but the big ones are rare - so only numbers below
+// DENSE_LIMIT get a slot in an array, and the rest go in a hash map.
+const DENSE_LIMIT: usize = 1 << 22;
+
+struct MemoryGame {
+    starting_nums: Vec<u32>,
+    turn: u32,
+    next_number: u32,
+    last_seen: Vec<u32>, // Turn number, or 0 if never seen
+    last_seen_sparse: HashMap<u32, u32>,
+}
+
+impl MemoryGame {
+    fn new(starting_nums: &[u32], dense_limit: usize) -> Self {
+        assert!(!starting_nums.is_empty(), "Invalid input: no starting numbers");
+        Self {
+            starting_nums: starting_nums.to_vec(),
+            turn: 0,
+            next_number: starting_nums[0],
+            last_seen: vec![0; dense_limit],
+            last_seen_sparse: HashMap::new(),
+        }
     }
-    let mut last_number = *starting_nums.last().unwrap();
-    let mut part1 = 0u64;
-    for turn in starting_nums.len()+1..=MAX_TURN {
-        let number = match last_seen[last_number] {
-            0 => 0,
-            turn_last_seen => turn - turn_last_seen - 1,
+
+    // Play until the last of the given turns (numbered from 1), and return the
+    // number spoken on each of them.
+    fn numbers_on_turns(starting_nums: &[u32], turns: &[usize]) -> Vec<u32> {
+        assert!(turns.iter().all(|&turn| turn > 0), "Turns are numbered from 1");
+        let last_turn = turns.iter().copied().max().unwrap_or(0);
+        assert!(last_turn < u32::MAX as usize, "Too many turns");
+        // In the order they come up, so each turn only has to be compared with
+        // the next one we want.
+        let mut wanted = turns.to_vec();
+        wanted.sort_unstable();
+        wanted.dedup();
+        let mut numbers: Vec<u32> = Vec::with_capacity(wanted.len());
+        let game = MemoryGame::new(starting_nums, last_turn.clamp(starting_nums.len(), DENSE_LIMIT));
+        for (index, number) in game.take(last_turn).enumerate() {
+            if wanted.get(numbers.len()) == Some(&(index + 1)) {
+                numbers.push(number);
+            }
+        }
+        turns.iter().map(|turn| numbers[wanted.binary_search(turn).unwrap()]).collect()
+    }
+
+    // Note that `number` was spoken on this turn, and return when it was last
+    // spoken before that.
+    fn speak(&mut self, number: u32) -> Option<u32> {
+        let previous = if (number as usize) < self.last_seen.len() {
+            std::mem::replace(&mut self.last_seen[number as usize], self.turn)
+        } else {
+            self.last_seen_sparse.insert(number, self.turn).unwrap_or(0)
+        };
+        if previous == 0 { None } else { Some(previous) }
+    }
+}
+
+impl Iterator for MemoryGame {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.turn = self.turn.checked_add(1)?;
+        let number = self.next_number;
+        let previous = self.speak(number);
+        self.next_number = match self.starting_nums.get(self.turn as usize) {
+            Some(&starting_num) => starting_num,
+            None => previous.map_or(0, |previous| self.turn - previous),
         };
-        last_seen[last_number] = turn-1;
-        last_number = number;
-        if turn == 2020 { part1 = number as u64; }
+        Some(number)
     }
-    let part2 = last_number as u64;
-    (part1,part2)
-}
\ No newline at end of file
+}
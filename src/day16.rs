@@ -1,7 +1,6 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use regex::Regex;
-
-const NUM_FIELDS: usize = 20;
+use crate::assignment::{self, Solution};
 
 // Don't even look at this, it's awful, I rushed it cos I had the opportunity to
 // be the first in my group to get today's star ;-)
@@ -9,8 +8,9 @@ const NUM_FIELDS: usize = 20;
 pub fn day16(input_lines: &[String]) -> (u64, u64) {
     for index in 0..input_lines.len() {
         if input_lines[index].is_empty() {
-            let mut fields = parse_fields(&input_lines[0..index]);
             let my_ticket: Vec<u64> = input_lines[index + 2].split(',').map(|num| num.parse::<u64>().expect("Invalid input")).collect();
+            let num_positions = my_ticket.len();
+            let mut fields = parse_fields(&input_lines[0..index], num_positions);
             let nearby_tickets: Vec<Vec<u64>> = input_lines[(index + 5)..].iter().map(|line| {
                 line.split(',').map(|num| num.parse::<u64>().expect("Invalid input")).collect()
             }).collect();
@@ -38,39 +38,33 @@ pub fn day16(input_lines: &[String]) -> (u64, u64) {
             let part1 = invalid_values.iter().sum();
 
             for ticket in valid_tickets {
-#[allow(clippy::needless_range_loop)]                
-                for position in 0..NUM_FIELDS {
+                for (position, val) in ticket.iter().enumerate() {
                     for field in fields.iter_mut() {
-                        let val = ticket[position];
-                        if val < field.min1 || (val > field.max1 && val < field.min2) || val > field.max2 {
-                            field.eliminate(position)
+                        if *val < field.min1 || (*val > field.max1 && *val < field.min2) || *val > field.max2 {
+                            field.potentially_valid_positions.remove(&position);
                         }
                     }
                 }
             }
 
-            loop {
-                let mut all_fields_solved = true;
-                let mut pos_to_eliminate: Vec<usize> = Vec::new();
-                for field in &fields {
-                    if let Some(position) = field.position {
-                        pos_to_eliminate.push(position);
-                    } else {
-                        all_fields_solved = false;
-                    }
-                }
-                for field in fields.iter_mut() {
-                    for pos in &pos_to_eliminate {
-                        field.eliminate(*pos);
-                    }
-                }
-                if all_fields_solved { break; }
-            }
+            let candidates = fields.iter().map(|field| field.potentially_valid_positions.clone()).collect();
+            let positions = match assignment::solve(candidates, num_positions) {
+                Solution::Unique(positions) => positions,
+                Solution::Multiple(positions, other_positions) => {
+                    let ambiguous: Vec<&str> = fields.iter().zip(positions.iter().zip(&other_positions))
+                        .filter(|(_, (position, other_position))| position != other_position)
+                        .map(|(field, _)| field.name.as_str())
+                        .collect();
+                    println!("The fields could be in more than one order (at least {:?} are ambiguous) - picking one", ambiguous);
+                    positions
+                },
+                Solution::Impossible => panic!("Invalid input: there's no way to fit the fields to the positions"),
+            };
 
             let mut departure_fields: Vec<u64> = Vec::new();
-            for field in fields.iter_mut() {
+            for (field, position) in fields.iter().zip(positions) {
                 if field.name.starts_with("departure") {
-                    departure_fields.push(my_ticket[field.position.unwrap()]);
+                    departure_fields.push(my_ticket[position]);
                 }
             }
             let part2 = departure_fields.iter().product();
@@ -81,7 +75,7 @@ pub fn day16(input_lines: &[String]) -> (u64, u64) {
     (0,0)
 }
 
-fn parse_fields(input_lines: &[String]) -> Vec<Field> {
+fn parse_fields(input_lines: &[String], num_positions: usize) -> Vec<Field> {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"^([^:]+): ([0-9]+)-([0-9]+) or ([0-9]+)-([0-9]+)$").unwrap();
     }
@@ -93,7 +87,7 @@ fn parse_fields(input_lines: &[String]) -> Vec<Field> {
             let max1 = caps.get(3).unwrap().as_str().parse::<u64>().unwrap();
             let min2 = caps.get(4).unwrap().as_str().parse::<u64>().unwrap();
             let max2 = caps.get(5).unwrap().as_str().parse::<u64>().unwrap();
-            fields.push(Field::new(name, min1, max1, min2, max2));
+            fields.push(Field::new(name, min1, max1, min2, max2, num_positions));
         }
     }
 
@@ -107,23 +101,11 @@ struct Field {
     max1: u64,
     min2: u64,
     max2: u64,
-    potentially_valid_positions: HashSet<usize>,
-    position: Option<usize>,
+    potentially_valid_positions: BTreeSet<usize>,
 }
 
 impl Field {
-    fn new(name: String, min1: u64, max1: u64, min2: u64, max2: u64) -> Self {
-        let mut set: HashSet<usize> = HashSet::with_capacity(NUM_FIELDS);
-        for position in 0..NUM_FIELDS {
-            set.insert(position);
-        }
-        Self { name, min1, max1, min2, max2, potentially_valid_positions: set, position: None }
-    }
-
-    fn eliminate(&mut self, position: usize) {
-        self.potentially_valid_positions.remove(&position);
-        if self.potentially_valid_positions.len() == 1 {
-            self.position = Some(*self.potentially_valid_positions.iter().next().unwrap());
-        }
+    fn new(name: String, min1: u64, max1: u64, min2: u64, max2: u64, num_positions: usize) -> Self {
+        Self { name, min1, max1, min2, max2, potentially_valid_positions: (0..num_positions).collect() }
     }
 }
\ No newline at end of file
@@ -63,7 +63,7 @@ impl PocketDimension {
             let active_neighbours = self.num_active_neighbours(&coords_so_far);
             if self.active_cubes.contains(&coords_so_far) {
                 // Cube is currently active
-                if active_neighbours < 2 || active_neighbours > 3 {
+                if !(2..=3).contains(&active_neighbours) {
                     self.current_iteration_changes.push((coords_so_far, false));
                 }
             } else {
@@ -84,7 +84,7 @@ impl PocketDimension {
     }
 
     fn num_active_neighbours(&self, pos: &[isize]) -> usize {
-        let mut active_neighbours = self.recursive_check_cube(&pos, 0);
+        let mut active_neighbours = self.recursive_check_cube(pos, 0);
         if self.active_cubes.contains(pos) {
             // We included ourselves in the recursive check - discount ourselves.
             active_neighbours -= 1;
@@ -1,104 +1,387 @@
-use std::rc::Rc;
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use regex::Regex;
+use crate::earley::{Grammar, Symbol};
 
 // "(Remember, you only need to handle the rules you have; building a solution that could handle any
 // hypothetical combination of rules would be significantly more difficult.)"
 //
-// Haha, screw you, I pretty much did it anyway.
+// Haha, screw you, I pretty much did it anyway.  And then went further: if the rules are regular, they're
+// compiled down to a single regex; if not, matching is done by a general Earley parser, so left recursion,
+// empty alternatives and non-ASCII messages are all fine too.
+//
+// Run with --parse-trees to see how each matching message was matched, or --verbose to hear about rules
+// that are never used, and about it when the rules can't be compiled to a regex.
 
 pub fn day19(input_lines: &[String]) -> (u64, u64) {
-    let mut map: HashMap<usize, Rc<RefCell<Rule>>> = HashMap::new();
+    let mut rules = RuleSet::default();
     let mut lines_iter = input_lines.iter();
-    while let Some(line) = lines_iter.next() {
+    for line in lines_iter.by_ref() {
         if line.is_empty() {
             break;
         }
-        parse_line(&mut map, line);
+        rules.parse_line(line);
     }
 
     let lines_iter_2 = lines_iter.clone();
-    let part1 = count_matching_messages(lines_iter, &map.get(&0).unwrap().borrow());
-    parse_line(&mut map, "8: 42 | 42 8");
-    parse_line(&mut map, "11: 42 31 | 42 11 31");
-    let part2 = count_matching_messages(lines_iter_2, &map.get(&0).unwrap().borrow());
+    rules.check(0);
+    let part1 = count_matching_messages(lines_iter, &rules);
+
+    // Part 2 only makes sense if the rules it replaces are actually in use.
+    let reachable = rules.reachable_from(0);
+    let in_use = |id: usize| rules.indices.get(&id).is_some_and(|index| reachable.contains(index));
+    if !in_use(8) || !in_use(11) {
+        println!("Rules 8 and 11 aren't both used by rule 0 - skipping part 2");
+        return (part1, 0);
+    }
+    rules.parse_line("8: 42 | 42 8");
+    rules.parse_line("11: 42 31 | 42 11 31");
+    rules.check(0);
+    let part2 = count_matching_messages(lines_iter_2, &rules);
     (part1,part2)
 }
 
-fn count_matching_messages<'a>(messages: impl Iterator<Item = &'a String>, rule: &Rule) -> u64 {
-    messages.filter(|line| rule.matches(line).contains(&line.len())).count() as u64
+fn count_matching_messages<'a>(messages: impl Iterator<Item = &'a String>, rules: &RuleSet) -> u64 {
+    let parse_trees = crate::utils::option_set("parse-trees");
+    let verbose = crate::utils::option_set("verbose");
+    if !parse_trees {
+        match compile_regex(rules, 0) {
+            Ok(regex) => return messages.filter(|line| regex.is_match(line)).count() as u64,
+            Err(NotRegular::Recursive(ids)) if verbose => println!("Rules {:?} can't be compiled to a regex - using the general parser", ids),
+            Err(NotRegular::TooLarge) if verbose => println!("Rules compile to too large a regex - using the general parser"),
+            Err(_) => (),
+        }
+    }
+
+    let grammar = rules.build_grammar(0);
+    if parse_trees {
+        messages.filter(|line| {
+            let tree = grammar.parse(0, line);
+            if let Some(tree) = &tree { println!("{}: {}", line, tree); }
+            tree.is_some()
+        }).count() as u64
+    } else {
+        messages.filter(|line| grammar.matches(0, line)).count() as u64
+    }
+}
+
+// Many rule sets (including part 1's) are regular, so can be compiled into a single regex that the regex
+// crate will chew through far faster than any parser.  Recursion is OK as long as it's a rule referring
+// directly to itself at the very start or very end of an alternative, like part 2's rule 8 - that's just
+// repetition.  Anything else, like part 2's rule 11, means the language might not be regular.
+enum NotRegular {
+    Recursive(Vec<usize>),
+    TooLarge,
+}
+
+fn compile_regex(rules: &RuleSet, start: usize) -> Result<Regex, NotRegular> {
+    let mut compiler = RegexCompiler { rules, compiled: HashMap::new(), in_progress: Vec::new(), blockers: BTreeSet::new() };
+    let pattern = compiler.compile(rules.indices[&start]);
+    if !compiler.blockers.is_empty() {
+        return Err(NotRegular::Recursive(compiler.blockers.into_iter().collect()));
+    }
+    Regex::new(&format!("^{}$", pattern)).map_err(|_| NotRegular::TooLarge)
+}
+
+// Works in terms of arena indices, but reports rule IDs.
+struct RegexCompiler<'a> {
+    rules: &'a RuleSet,
+    compiled: HashMap<usize, String>,
+    in_progress: Vec<usize>,
+    blockers: BTreeSet<usize>,
+}
+
+impl RegexCompiler<'_> {
+    fn compile(&mut self, index: usize) -> String {
+        if let Some(pattern) = self.compiled.get(&index) {
+            return pattern.clone();
+        }
+        if let Some(position) = self.in_progress.iter().position(|&other| other == index) {
+            // We've looped back round to a rule we're still compiling, via some other rules - everything
+            // on that loop is to blame.
+            let rules = self.rules;
+            self.blockers.extend(self.in_progress[position..].iter().map(|&looped| rules.arena[looped].id));
+            return String::new();
+        }
+
+        self.in_progress.push(index);
+        let pattern = match &self.rules.arena[index].match_type {
+            MatchType::Patterns => self.compile_patterns(index),
+            MatchType::Unresolved => unreachable!("Rules are validated when loaded"),
+        };
+        self.in_progress.pop();
+
+        self.compiled.insert(index, pattern.clone());
+        pattern
+    }
+
+    fn compile_patterns(&mut self, index: usize) -> String {
+        // Split the alternatives into those that don't refer to this rule at all, those that end with this
+        // rule (so can repeat before the rest), and those that start with this rule (so can repeat after).
+        let rules = self.rules;
+        let mut base: Vec<String> = Vec::new();
+        let mut repeat_before: Vec<String> = Vec::new();
+        let mut repeat_after: Vec<String> = Vec::new();
+        for pattern in &rules.arena[index].patterns {
+            let self_references = pattern.iter().filter(|element| element.is_rule(index)).count();
+            if self_references == 0 {
+                base.push(self.compile_sequence(pattern));
+            } else if self_references == 1 && pattern.len() > 1 && pattern[pattern.len() - 1].is_rule(index) {
+                repeat_before.push(self.compile_sequence(&pattern[..pattern.len() - 1]));
+            } else if self_references == 1 && pattern.len() > 1 && pattern[0].is_rule(index) {
+                repeat_after.push(self.compile_sequence(&pattern[1..]));
+            } else {
+                self.blockers.insert(rules.arena[index].id);
+            }
+        }
+
+        // Recursing at both ends, or having nowhere for the recursion to stop, is too hard.
+        if base.is_empty() || (!repeat_before.is_empty() && !repeat_after.is_empty()) {
+            self.blockers.insert(rules.arena[index].id);
+        }
+
+        let mut pattern = String::new();
+        if !repeat_before.is_empty() { pattern.push_str(&format!("(?:{})*", repeat_before.join("|"))); }
+        pattern.push_str(&format!("(?:{})", base.join("|")));
+        if !repeat_after.is_empty() { pattern.push_str(&format!("(?:{})*", repeat_after.join("|"))); }
+        pattern
+    }
+
+    fn compile_sequence(&mut self, elements: &[Element]) -> String {
+        elements.iter().map(|element| match element {
+            Element::Rule(subrule) => self.compile(*subrule),
+            Element::Terminal(s) => regex::escape(s),
+        }).collect()
+    }
+}
+
+// All the rules live in an arena, and refer to each other by their index in it.  That way self-referential
+// rules like "8: 42 | 42 8" are no trouble.  Rules can be referenced before they're defined, in which case
+// they sit in the arena as Unresolved until their definition turns up.  Quoted strings can go anywhere in
+// an alternative, alongside references to other rules, so `4: "a"` is just a rule whose only alternative
+// is a single string.
+#[derive(Default)]
+struct RuleSet {
+    arena: Vec<Rule>,
+    indices: HashMap<usize, usize>,
+}
+
+impl RuleSet {
+    fn parse_line(&mut self, line: &str) {
+        let mut tokens = line.split(' ');
+        let mut first_token = tokens.next().unwrap().to_string();
+        first_token.truncate(first_token.len() - 1);
+        let rule_id = first_token.parse::<usize>().expect("Invalid input");
+        let index = self.get_rule(rule_id);
+
+        let mut patterns: Vec<Pattern> = Vec::new();
+        let mut pattern = Pattern::new();
+        for token in tokens.filter(|token| !token.is_empty()).map(parse_token) {
+            match token {
+                ParseResult::String(s) => pattern.push(Element::Terminal(s)),
+                ParseResult::Number(num) => pattern.push(Element::Rule(self.get_rule(num))),
+                ParseResult::Pipe => { patterns.push(pattern); pattern = Pattern::new(); }
+            }
+        }
+        patterns.push(pattern);
+
+        let rule = &mut self.arena[index];
+        rule.match_type = MatchType::Patterns;
+        rule.patterns = patterns;
+    }
+
+    fn get_rule(&mut self, id: usize) -> usize {
+        let arena = &mut self.arena;
+        *self.indices.entry(id).or_insert_with(|| {
+            arena.push(Rule::new(id));
+            arena.len() - 1
+        })
+    }
+
+    // Only the rules the start rule can get to go into the grammar - the rest are never used, and don't
+    // even have to be defined.  Undefined rules are left out, so they never match anything.
+    fn build_grammar(&self, start: usize) -> Grammar {
+        let mut grammar = Grammar::new();
+        let mut reachable: Vec<usize> = self.reachable_from(start).into_iter().collect();
+        reachable.sort_unstable();
+        for rule in reachable.into_iter().map(|index| &self.arena[index]) {
+            let alternatives = match &rule.match_type {
+                MatchType::Patterns => rule.patterns.iter().map(|pattern| {
+                    pattern.iter().map(|element| match element {
+                        Element::Rule(subrule) => Symbol::Rule(self.arena[*subrule].id),
+                        Element::Terminal(s) => Symbol::Terminal(s.clone()),
+                    }).collect()
+                }).collect(),
+                MatchType::Unresolved => continue,
+            };
+            grammar.set_rule(rule.id, alternatives);
+        }
+        grammar
+    }
+
+    // Give up if any problems with the rules would stop us matching, and with --verbose, mention the
+    // harmless ones too.
+    fn check(&self, start: usize) {
+        let errors = self.validate(start);
+        if crate::utils::option_set("verbose") {
+            for error in errors.iter().filter(|error| !error.is_fatal()) {
+                println!("Warning: {}", error);
+            }
+        }
+        let fatal: Vec<String> = errors.iter().filter(|error| error.is_fatal()).map(ToString::to_string).collect();
+        if !fatal.is_empty() {
+            panic!("Invalid rules:\n{}", fatal.join("\n"));
+        }
+    }
+
+    fn validate(&self, start: usize) -> Vec<RuleError> {
+        let mut errors: Vec<RuleError> = Vec::new();
+        if !self.indices.contains_key(&start) {
+            errors.push(RuleError::Undefined(start, Vec::new()));
+            return errors;
+        }
+
+        // Rules that can't be reached from the start rule.  Only the rest matter from here on.
+        let reachable = self.reachable_from(start);
+        let mut unreachable: Vec<usize> = (0..self.arena.len()).filter(|index| !reachable.contains(index)).map(|index| self.arena[index].id).collect();
+        if !unreachable.is_empty() {
+            unreachable.sort_unstable();
+            errors.push(RuleError::Unreachable(start, unreachable));
+        }
+
+        // Rules that were referenced but never defined.
+        let mut undefined: Vec<&Rule> = reachable.iter().map(|&index| &self.arena[index]).filter(|rule| rule.match_type == MatchType::Unresolved).collect();
+        undefined.sort_by_key(|rule| rule.id);
+        for rule in undefined {
+            let index = self.indices[&rule.id];
+            let mut referenced_by: Vec<usize> = self.arena.iter()
+                .filter(|other| other.patterns.iter().flatten().any(|element| element.is_rule(index)))
+                .map(|other| other.id)
+                .collect();
+            referenced_by.sort_unstable();
+            errors.push(RuleError::Undefined(rule.id, referenced_by));
+        }
+
+        // Rules that can end up back at themselves without consuming any input.  Rule A can get to rule
+        // B that way if one of A's alternatives contains B and everything else in that alternative could
+        // match the empty string.
+        let grammar = self.build_grammar(start);
+        let nullable: HashSet<usize> = reachable.iter().copied().filter(|&index| grammar.is_nullable(self.arena[index].id)).collect();
+        let empty_steps: Vec<Vec<usize>> = self.arena.iter().map(|rule| {
+            let mut steps: Vec<usize> = Vec::new();
+            for pattern in &rule.patterns {
+                for (position, subrule) in pattern.iter().enumerate().filter_map(|(position, element)| Some((position, element.rule()?))) {
+                    let others_nullable = pattern.iter().enumerate().all(|(other_position, other)| other_position == position || other.is_nullable(&nullable));
+                    if others_nullable { steps.push(subrule); }
+                }
+            }
+            steps
+        }).collect();
+        let reaches: Vec<HashSet<usize>> = (0..self.arena.len()).map(|index| {
+            let mut seen: HashSet<usize> = HashSet::new();
+            let mut to_visit = empty_steps[index].clone();
+            while let Some(next) = to_visit.pop() {
+                if seen.insert(next) { to_visit.extend(&empty_steps[next]); }
+            }
+            seen
+        }).collect();
+        let mut reported: HashSet<usize> = HashSet::new();
+        for index in 0..self.arena.len() {
+            if !reachable.contains(&index) || !reaches[index].contains(&index) || reported.contains(&index) { continue; }
+            let cycle: Vec<usize> = reaches[index].iter().copied().filter(|&other| reaches[other].contains(&index)).collect();
+            reported.extend(&cycle);
+            let mut ids: Vec<usize> = cycle.into_iter().map(|member| self.arena[member].id).collect();
+            ids.sort_unstable();
+            errors.push(RuleError::NonConsumingCycle(ids));
+        }
+
+        errors
+    }
+
+    // Arena indices of the start rule and everything it refers to, directly or not.
+    fn reachable_from(&self, start: usize) -> HashSet<usize> {
+        let mut reachable: HashSet<usize> = HashSet::new();
+        let mut to_visit: Vec<usize> = self.indices.get(&start).copied().into_iter().collect();
+        while let Some(index) = to_visit.pop() {
+            if reachable.insert(index) {
+                to_visit.extend(self.arena[index].patterns.iter().flatten().filter_map(Element::rule));
+            }
+        }
+        reachable
+    }
+}
+
+enum RuleError {
+    Undefined(usize, Vec<usize>),
+    Unreachable(usize, Vec<usize>),
+    NonConsumingCycle(Vec<usize>),
+}
+
+impl RuleError {
+    // Unreachable rules are harmless - they just never get used.
+    fn is_fatal(&self) -> bool {
+        !matches!(self, RuleError::Unreachable(_, _))
+    }
+}
+
+impl std::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleError::Undefined(id, referenced_by) if referenced_by.is_empty() => write!(f, "rule {} is never defined", id),
+            RuleError::Undefined(id, referenced_by) => write!(f, "rule {} is never defined, but is used by rules {:?}", id, referenced_by),
+            RuleError::Unreachable(start, ids) => write!(f, "rules {:?} can't be reached from rule {}", ids, start),
+            RuleError::NonConsumingCycle(ids) => write!(f, "rules {:?} can recurse forever without consuming any input", ids),
+        }
+    }
 }
 
 #[derive(PartialEq)]
 enum MatchType {
-    String(String),
     Patterns,
     Unresolved,
 }
 
-type Pattern = Vec<Rc<RefCell<Rule>>>;
+// Rules are referred to by their index in the arena.
+enum Element {
+    Rule(usize),
+    Terminal(String),
+}
+
+impl Element {
+    fn rule(&self) -> Option<usize> {
+        match self {
+            Element::Rule(index) => Some(*index),
+            Element::Terminal(_) => None,
+        }
+    }
+
+    fn is_rule(&self, index: usize) -> bool {
+        self.rule() == Some(index)
+    }
+
+    fn is_nullable(&self, nullable_rules: &HashSet<usize>) -> bool {
+        match self {
+            Element::Rule(index) => nullable_rules.contains(index),
+            Element::Terminal(s) => s.is_empty(),
+        }
+    }
+}
+
+type Pattern = Vec<Element>;
 
 struct Rule {
+    id: usize,
     match_type: MatchType,
     patterns: Vec<Pattern>,
 }
 
 impl Rule {
-    fn new() -> Self {
+    fn new(id: usize) -> Self {
         Self {
+            id,
             match_type: MatchType::Unresolved,
             patterns: Vec::new(),
         }
     }
-
-    fn parse(&mut self, map: &mut HashMap<usize, Rc<RefCell<Rule>>>, tokens: impl Iterator<Item = ParseResult>) {
-        let mut pattern = Pattern::new();
-        self.match_type = MatchType::Patterns;
-        for token in tokens {
-            match token {
-                ParseResult::String(s) => self.match_type = MatchType::String(s),
-                ParseResult::Number(num) => pattern.push(get_rule(map, num)),
-                ParseResult::Pipe => { self.patterns.push(pattern); pattern = Pattern::new(); }
-            }
-        }
-        if self.match_type == MatchType::Patterns { self.patterns.push(pattern); }
-    }
-
-    fn matches(&self, input: &str) -> HashSet<usize> {
-        let mut set: HashSet<usize> = HashSet::new();
-        match &self.match_type {
-            MatchType::String(s) => if input.starts_with(s) { set.insert(s.len()); },
-            MatchType::Patterns => {
-                for pattern in &self.patterns {
-                    let mut this_pattern_set: HashSet<usize> = HashSet::new();
-                    this_pattern_set.insert(0);
-
-                    for subrule_cell in pattern {
-                        // Going to be cheeky today and assume ASCII input - proper UTF-8 support can wait for another
-                        // time.  I already demonstrated it in day 18.
-                        if this_pattern_set.is_empty() {
-                            this_pattern_set.insert(0);
-                        }
-                        let mut replacement_set: HashSet<usize> = HashSet::new();
-                        for previous_length in this_pattern_set {
-                            let subrule_matches = subrule_cell.borrow().matches(&input[previous_length..]);
-                            for length in subrule_matches {
-                                replacement_set.insert(previous_length + length);
-                            }
-                        }
-                        this_pattern_set = replacement_set;
-                        if this_pattern_set.is_empty() {
-                            break;
-                        }
-                    }
-
-                    set.extend(this_pattern_set);
-                }
-            },
-            MatchType::Unresolved => unreachable!("Rule missing from input"),
-        }
-        set
-    }
 }
 
 enum ParseResult {
@@ -107,17 +390,6 @@ enum ParseResult {
     Pipe,
 }
 
-fn parse_line(map: &mut HashMap<usize, Rc<RefCell<Rule>>>, line: &str) {
-    let mut tokens = line.split(' ');
-    let mut first_token = tokens.next().unwrap().to_string();
-    first_token.truncate(first_token.len() - 1);
-    let rule_id = first_token.parse::<usize>().expect("Invalid input");
-    let rule_cell = get_rule(map, rule_id);
-    let mut rule = rule_cell.borrow_mut();
-    let tokens = tokens.map(|token| parse_token(token));
-    rule.parse(map, tokens);
-}
-
 fn parse_token(token: &str) -> ParseResult {
     let mut chars = token.chars();
     match chars.next().unwrap() {
@@ -137,7 +409,3 @@ fn parse_token(token: &str) -> ParseResult {
         }
     }
 }
-
-fn get_rule(map: &mut HashMap<usize, Rc<RefCell<Rule>>>, index: usize) -> Rc<RefCell<Rule>> {
-    map.entry(index).or_insert_with(|| Rc::new(RefCell::new(Rule::new()))).clone()
-}
\ No newline at end of file
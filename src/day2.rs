@@ -1,3 +1,84 @@
+use regex::Regex;
+use crate::utils;
+
+// Every line gives two numbers, a character and a password, and each policy
+// reads the numbers in its own way.  Part 1 uses the "count" policy and part 2
+// the "positions" one; run with --policies=<name>[,<name>...] to check the
+// passwords against any of the policies in POLICIES as well, and see which of
+// them each password failed.  --policy=regex:<pattern> adds a policy of your
+// own: the password has to match the regex somewhere (anchor it with ^ and $ to
+// match all of it).
+
+struct NamedPolicy {
+    name: &'static str,
+    description: &'static str,
+    build: fn() -> Box<dyn PasswordPolicy>,
+}
+
+const POLICIES: &[NamedPolicy] = &[
+    NamedPolicy { name: "count", description: "the character appears between the first and second numbers of times", build: || Box::new(CountPolicy) },
+    NamedPolicy { name: "positions", description: "the character is at exactly one of the two (1-based) positions", build: || Box::new(PositionsPolicy) },
+    NamedPolicy { name: "length", description: "the password is between the first and second numbers of characters long", build: || Box::new(LengthPolicy) },
+    NamedPolicy { name: "run", description: "somewhere the character appears between the first and second numbers of times in a row", build: || Box::new(RunPolicy) },
+];
+
+trait PasswordPolicy {
+    fn is_valid(&self, password: &PasswordAndPolicy) -> bool;
+}
+
+struct CountPolicy;
+
+impl PasswordPolicy for CountPolicy {
+    fn is_valid(&self, password: &PasswordAndPolicy) -> bool {
+        let char_count = password.password.matches(password.required_char).count();
+        (char_count >= password.num_one) && (char_count <= password.num_two)
+    }
+}
+
+// The positions are checked independently, so if they're the same position the
+// character can never be at exactly one of them.  Position 0 doesn't exist, and
+// neither does anything past the end of the password.
+struct PositionsPolicy;
+
+impl PasswordPolicy for PositionsPolicy {
+    fn is_valid(&self, password: &PasswordAndPolicy) -> bool {
+        let char_at = |position: usize| position.checked_sub(1).and_then(|index| password.password.chars().nth(index));
+        let first_char_matches = char_at(password.num_one) == Some(password.required_char);
+        let second_char_matches = char_at(password.num_two) == Some(password.required_char);
+        first_char_matches ^ second_char_matches
+    }
+}
+
+struct LengthPolicy;
+
+impl PasswordPolicy for LengthPolicy {
+    fn is_valid(&self, password: &PasswordAndPolicy) -> bool {
+        let length = password.password.chars().count();
+        (length >= password.num_one) && (length <= password.num_two)
+    }
+}
+
+// Runs are as long as they can be, so a longer run doesn't count as a shorter
+// one as well.
+struct RunPolicy;
+
+impl PasswordPolicy for RunPolicy {
+    fn is_valid(&self, password: &PasswordAndPolicy) -> bool {
+        password.password.split(|c| c != password.required_char)
+            .map(|run| run.chars().count())
+            .any(|length| length >= password.num_one && length <= password.num_two)
+    }
+}
+
+// Compiled once up front, not once per password.
+struct RegexPolicy(Regex);
+
+impl PasswordPolicy for RegexPolicy {
+    fn is_valid(&self, password: &PasswordAndPolicy) -> bool {
+        self.0.is_match(password.password)
+    }
+}
+
 struct PasswordAndPolicy<'a> {
     // The first and second numbers in the policy.
     num_one: usize,
@@ -18,7 +99,7 @@ impl<'a> PasswordAndPolicy<'a> {
         let input_line = &input_line[space_pos + 1..];
         let required_char = input_line.chars().next().expect("Invalid password policy: required character missing");
         // Skip over the required character, the colon and the space
-        let password = &input_line[3..];
+        let password = input_line[required_char.len_utf8()..].strip_prefix(": ").expect("Invalid password policy: no colon after required character");
     
         PasswordAndPolicy {
             num_one,
@@ -27,28 +108,47 @@ impl<'a> PasswordAndPolicy<'a> {
             password
         }        
     }
+}
 
-    fn valid_for_part_one(&self) -> bool {
-        // Interpret the two numbers as the minimum and maximum times the required_char may appear.
-        let char_count = self.password.matches(self.required_char).count();
-        (char_count >= self.num_one) && (char_count <= self.num_two)        
+pub fn day2(input_lines: &[String]) -> (u64, u64) {
+    let mut policy_names = vec!["count", "positions"];
+    let chosen_policies: Vec<String> = utils::option_value("policies").map_or(Vec::new(), |policies| policies.split(',').map(str::to_string).collect());
+    for name in &chosen_policies {
+        if !policy_names.contains(&name.as_str()) {
+            policy_names.push(POLICIES.iter().find(|policy| policy.name == name).map(|policy| policy.name).unwrap_or_else(|| {
+                let known: Vec<&str> = POLICIES.iter().map(|policy| policy.name).collect();
+                panic!("Unknown policy {} - try one of {}", name, known.join(", "))
+            }));
+        }
     }
-
-    fn valid_for_part_two(&self) -> bool {
-        // Interpret the two numbers as the 1-based indices that the required_char may appear,
-        // and require that it appears in precisely one of those positions.
-        // Using nth twice on the same iterator avoids needlessly iterating over early characters twice,
-        // but requires some arithmetic to figure out the second index.
-        let mut chars = self.password.chars();
-        let first_char_matches = chars.nth(self.num_one - 1).unwrap_or_default() == self.required_char;
-        let second_char_matches = chars.nth(self.num_two - self.num_one - 1).unwrap_or_default() == self.required_char;
-        first_char_matches ^ second_char_matches
+    // Name, description and the policy itself.
+    let mut policies: Vec<(String, String, Box<dyn PasswordPolicy>)> = policy_names.iter().map(|&name| {
+        let named = POLICIES.iter().find(|policy| policy.name == name).unwrap();
+        (named.name.to_string(), named.description.to_string(), (named.build)())
+    }).collect();
+    let custom_policy = utils::option_value("policy");
+    if let Some(custom) = &custom_policy {
+        let pattern = custom.strip_prefix("regex:").expect("Invalid --policy - expected regex:<pattern>");
+        let regex = Regex::new(pattern).unwrap_or_else(|error| panic!("Invalid --policy regex {}: {}", pattern, error));
+        policies.push(("regex".to_string(), format!("the password matches {}", pattern), Box::new(RegexPolicy(regex))));
     }
-}
 
-pub fn day2(input_lines: &[String]) -> (u64, u64) {
     let passwords: Vec<PasswordAndPolicy> = input_lines.iter().map(|line| PasswordAndPolicy::new(line)).collect();
-    let part1 = passwords.iter().filter(|password| password.valid_for_part_one()).count() as u64;
-    let part2 = passwords.iter().filter(|password| password.valid_for_part_two()).count() as u64;
+    // Which of the policies each password is valid for, in the same order.
+    let results: Vec<Vec<bool>> = passwords.iter().map(|password| policies.iter().map(|(_, _, policy)| policy.is_valid(password)).collect()).collect();
+    let valid_count = |index: usize| results.iter().filter(|valid| valid[index]).count();
+    let part1 = valid_count(0) as u64;
+    let part2 = valid_count(1) as u64;
+
+    if !chosen_policies.is_empty() || custom_policy.is_some() {
+        for (line, valid) in input_lines.iter().zip(&results) {
+            let failed: Vec<&str> = policies.iter().zip(valid).filter(|(_, &valid)| !valid).map(|((name, _, _), _)| name.as_str()).collect();
+            println!("{}: {}", line, if failed.is_empty() { "passed everything".to_string() } else { format!("failed {}", failed.join(", ")) });
+        }
+        for (index, (name, description, _)) in policies.iter().enumerate() {
+            println!("{} ({}): {} valid", name, description, valid_count(index));
+        }
+    }
+
     (part1, part2)
-}
\ No newline at end of file
+}
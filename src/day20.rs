@@ -1,389 +1,665 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use crate::utils;
 
-const TILE_SIZE: usize = 10;
-const SQUARE_SIDE: usize = 12;
-const STRIPPED_TILE_SIZE: usize = TILE_SIZE - 2;
-const IMAGE_SIZE: usize = SQUARE_SIDE * STRIPPED_TILE_SIZE;
-
-const SEA_MONSTER_ROWS: usize = 3;
-const SEA_MONSTER: [&str; SEA_MONSTER_ROWS] = [
+const SEA_MONSTER: [&str; 3] = [
     "                  # ",
     "#    ##    ##    ###",
     " #  #  #  #  #  #   ",
 ];
-const SEA_MONSTER_COLS: usize = SEA_MONSTER[0].len();
-lazy_static! {
-    static ref SEA_MONSTER_CELLS: Vec<(usize, usize)> = {
-        let mut cells: Vec<(usize, usize)> = Vec::new();
-        for (row_index, row) in SEA_MONSTER.iter().enumerate() {
-            for (col_index, cell) in row.chars().enumerate() {
-                if cell == '#' { cells.push((row_index, col_index)); }
+
+// Run with --tiles to see where each tile ended up and which way round it is.
+// If the tiles fit together in more than one way, every way is listed; to stop
+// looking after n of them, run with --max-arrangements=<n>.
+//
+// Run with --patterns=<file>[,<file>...] to search the picture for other things
+// than sea monsters.  Each file is an ASCII picture of the pattern: '#' must be
+// set in the image, anything else (e.g. a space) doesn't matter.
+//
+// Run with --export=<file> to save the finished picture, with the sea monsters
+// highlighted, as a .pbm, .ppm, .png or .svg.  Add --seams to mark where the
+// tiles join.  The SVG also labels each tile with its ID and orientation.
+
+pub fn day20(input_lines: &[String]) -> (u64, u64) {
+    let tiles: Vec<Tile> = utils::group_lines_split_by_empty_line(input_lines).into_iter()
+        .filter(|lines| !lines.is_empty())
+        .map(Tile::parse)
+        .collect();
+
+    let limit = utils::option_value("max-arrangements").map(|limit| limit.parse::<usize>().ok().filter(|&limit| limit > 0).expect("Invalid --max-arrangements"));
+    let solutions = Assembler::new(&tiles).solve(limit.unwrap_or(usize::MAX));
+    if solutions.is_empty() {
+        panic!("Invalid input: there's no way to fit the tiles together into a rectangle");
+    }
+    if solutions.len() > 1 {
+        if limit == Some(solutions.len()) {
+            println!("The tiles fit together in at least {} ways (stopped looking there - there may be more):", solutions.len());
+        } else {
+            println!("The tiles fit together in {} ways:", solutions.len());
+        }
+        for (index, assembly) in solutions.iter().enumerate() {
+            println!("Arrangement {}:\n{}", index + 1, assembly);
+        }
+    } else if utils::option_set("tiles") {
+        println!("Arrangement:\n{}", solutions[0]);
+    }
+
+    // If there's more than one way to do it, just go with the first.
+    let assembly = &solutions[0];
+    let part1: u64 = assembly.corners().product();
+    let image = Image::construct(&tiles, assembly);
+    let sea_monster = Pattern::parse("sea monster", SEA_MONSTER.iter().copied());
+    let all_sea_monsters = image.find_matches(&sea_monster);
+    let sea_monsters = in_best_orientation(&all_sea_monsters);
+    let part2: u64 = image.roughness(&sea_monsters) as u64;
+
+    if let Some(file) = utils::option_value("export") {
+        let highlights: HashSet<(usize, usize)> = sea_monsters.iter().flat_map(|found| found.cells.iter().copied()).collect();
+        image.export(&file, assembly, &highlights, utils::option_set("seams"));
+    }
+
+    if let Some(files) = utils::option_value("patterns") {
+        for file in files.split(',') {
+            let contents = std::fs::read_to_string(file).expect("Can't open/read pattern file");
+            let pattern = Pattern::parse(file, contents.lines());
+            let all_matches = image.find_matches(&pattern);
+            let matches = in_best_orientation(&all_matches);
+            let best = matches.first().map_or(String::new(), |found| format!(" ({} in orientation {}, which the roughness counts)", matches.len(), found.orientation));
+            println!("{}: {} matches{}, roughness {}", pattern.name, all_matches.len(), best, image.roughness(&matches));
+            for found in &all_matches {
+                println!("  row {}, col {}, orientation {}", found.row, found.col, found.orientation);
             }
         }
-        cells
-    };
-}
+    }
 
-pub fn day20(input_lines: &[String]) -> (u64, u64) {
-    let mut image = Image::construct(input_lines);
-    let part1: u64 = Image::find_corners(&image.tiles_by_id).product();
-    let part2: u64 = image.water_roughness() as u64;
     (part1,part2)
 }
 
-struct Image {
-    tiles_by_id: HashMap<u64, Tile>,
-    data: [[bool; IMAGE_SIZE]; IMAGE_SIZE],
+// A tile (or image) is reoriented by flipping it left-to-right, if `flipped`,
+// and then rotating it clockwise through 90 degrees `rotations` times.  That
+// covers all eight ways it could be arranged.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Orientation {
+    flipped: bool,
+    rotations: usize,
 }
 
-impl std::fmt::Display for Image {
+impl std::fmt::Display for Orientation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut output = String::with_capacity((SQUARE_SIDE + 1) * SQUARE_SIDE);
-        for row in self.data.iter() {
-            for cell in row {
-                if *cell { output.push('#'); } else { output.push('.'); }
-            }
-            output.push('\n');
+        if self.flipped { write!(f, "flip+")?; }
+        write!(f, "{}", self.rotations * 90)
+    }
+}
+
+impl Orientation {
+    fn all() -> impl Iterator<Item = Self> {
+        [false, true].iter().flat_map(|&flipped| (0..4).map(move |rotations| Self { flipped, rotations }))
+    }
+
+    // The orientation you get by applying this one and then the next.  Flipping
+    // after rotating is the same as flipping first and then rotating the other
+    // way.
+    fn then(self, next: Self) -> Self {
+        let rotations = if next.flipped { 4 - self.rotations } else { self.rotations };
+        Self { flipped: self.flipped != next.flipped, rotations: (rotations + next.rotations) % 4 }
+    }
+
+    // Reorient a grid of cells.  The grid doesn't need to be square.
+    fn apply<T: Copy>(self, data: &[Vec<T>]) -> Vec<Vec<T>> {
+        let mut result: Vec<Vec<T>> = if self.flipped {
+            data.iter().map(|row| row.iter().rev().copied().collect()).collect()
+        } else {
+            data.to_vec()
+        };
+        for _ in 0..self.rotations {
+            let rows = result.len();
+            let cols = result.first().map_or(0, Vec::len);
+            result = (0..cols).map(|col| (0..rows).rev().map(|row| result[row][col]).collect()).collect();
         }
-        write!(f, "{}", output)
+        result
     }
 }
 
-impl Image {
-    fn construct(input_lines: &[String]) -> Self {
-        let mut tiles_by_id = Self::construct_tiles(input_lines);
-        let tiles = Self::arrange_tiles(&mut tiles_by_id);
-        let data = Self::construct_image(&tiles_by_id, &tiles);
-        Self { tiles_by_id, data }
-    }
-
-    fn construct_tiles(input_lines: &[String]) -> HashMap<u64, Tile> {
-        let mut tiles: HashMap<u64, Tile> = HashMap::new();
-        let mut edges: HashMap<String, u64> = HashMap::new();
-
-        // We know how many lines each tile will contain - use that to iterate
-        // over chunks of lines.
-        for input in input_lines.chunks(TILE_SIZE + 2) {
-            // Parse the chunk into a Tile structure.
-            let mut tile = Tile::parse(input);
-
-            // Try to match up each edge to a tile we've already parsed. We'll
-            // worry about rotation/flipping later, so for now consider any edge
-            // to any other edge, including matching 1234 to 4321.
-            for index in 0..4 {
-                if let Some(partner_id) = edges.get(&tile.edges[index]) {
-                    tiles.get_mut(partner_id).unwrap().edge_matched(&tile.edges[index], tile.id);
-                    tile.partners[index] = Some(*partner_id);
-                    edges.remove(&tile.edges[index]);
-                } else {
-                    let opposite_edge: String = tile.edges[index].chars().rev().collect();
-                    if let Some(partner_id) = edges.get(&opposite_edge) {
-                        tiles.get_mut(partner_id).unwrap().edge_matched(&opposite_edge, tile.id);
-                        tile.partners[index] = Some(*partner_id);
-                        edges.remove(&opposite_edge);
-                    } else {
-                        // We haven't matched to a previous tile, so store off
-                        // for a future match.
-                        edges.insert(tile.edges[index].clone(), tile.id);
-                    }
-                }
-            }
-            tiles.insert(tile.id, tile);
+// Where one tile goes in the finished picture, and which way round.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Placement {
+    id: u64,
+    orientation: Orientation,
+}
+
+// A complete arrangement of the tiles, stored row by row.
+#[derive(Clone)]
+struct Assembly {
+    width: usize,
+    cells: Vec<Placement>,
+}
+
+impl std::fmt::Display for Assembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in self.cells.chunks(self.width) {
+            let cells: Vec<String> = row.iter().map(|cell| format!("{:>5} {:<8}", cell.id, cell.orientation.to_string())).collect();
+            writeln!(f, "{}", cells.join(" ").trim_end())?;
         }
-        tiles
-    }
-
-    fn arrange_tiles(map: &mut HashMap<u64, Tile>) -> [[u64; SQUARE_SIDE]; SQUARE_SIDE] {
-        let mut tiles = [[0u64; SQUARE_SIDE]; SQUARE_SIDE];
-
-        // Each tile now knows which other tiles border it, but we haven't oriented or
-        // arranged them - do so now.
-        //
-        // The logic is:
-        // -  Choose an arbitrary corner tile to be at the top-left.  (We know which tiles
-        //    are in the corners because they have two bordering tiles.)
-        // -  Rotate that tile so that it has partners below and to the right.
-        // -  Work along the row: slot our right-hand partner into place, and align it so
-        //    that its edge matches up to ours; repeat all the way along the row.
-        // -  Do the same thing for our partner below to kick off the next row, and then
-        //    work all the way along THAT row. Repeat for all rows.
-        //
-        // This code would be neater if we could just have variables for Tiles themselves,
-        // rather than separately remembering a bunch of IDs and edges, but the borrow
-        // checker makes it tricky to hold references to multiple things in the HashMap
-        // simultaneously when one of them needs to be mutable.  We could do better,
-        // especially if we made the contents of the map RefCell<Tile>, but I've done
-        // enough of that for one AoC.
-        let mut first_tile_id_in_previous_row: Option<u64> = None;
-        let mut bottom_edge_in_previous_row = String::new();
-        for row in tiles.iter_mut() {
-            let first_tile_in_row = if let Some(previous_tile_id) = first_tile_id_in_previous_row {
-                // This is not the first row - find the tile that goes below the
-                // one at the start of the previous row, and align it to fit
-                // below.
-                let previous_tile = map.get(&previous_tile_id).unwrap();
-                let new_tile_id = previous_tile.partners[2].unwrap();
-                let new_tile = map.get_mut(&new_tile_id).unwrap();
-                new_tile.align_to_below(previous_tile_id, &bottom_edge_in_previous_row);
-                new_tile
-            } else {
-                // This is the first row.
-                // Select an arbitrary corner tile to be the top-left corner.
-                let new_tile_id = Self::find_corners(map).next().unwrap();
-                let new_tile = map.get_mut(&new_tile_id).unwrap();
-                new_tile.align_to_top_left_corner();
-                new_tile
-            };
+        Ok(())
+    }
+}
+
+impl Assembly {
+    fn rows(&self) -> Vec<Vec<Placement>> {
+        self.cells.chunks(self.width).map(<[Placement]>::to_vec).collect()
+    }
+
+    fn corners(&self) -> impl Iterator<Item = u64> + '_ {
+        // A BTreeSet, because a one-tile-wide assembly has fewer than four
+        // distinct corners.
+        let last = self.cells.len() - 1;
+        let positions: BTreeSet<usize> = [0, self.width - 1, last + 1 - self.width, last].iter().copied().collect();
+        positions.into_iter().map(move |position| self.cells[position].id)
+    }
+
+    // The same assembly, with the whole picture flipped/rotated.
+    fn transformed(&self, transform: Orientation) -> Self {
+        let reoriented: Vec<Vec<Placement>> = self.rows().into_iter().map(|row| row.into_iter().map(|cell| {
+            Placement { id: cell.id, orientation: cell.orientation.then(transform) }
+        }).collect()).collect();
+        let rows = transform.apply(&reoriented);
+        Self { width: rows[0].len(), cells: rows.into_iter().flatten().collect() }
+    }
+
+    // Every assembly is one of a family of (up to) eight, all of which are the
+    // same picture just flipped/rotated.  We only want to report one of each
+    // family, so pick the one that sorts first - which, going by the width
+    // first, is never wider than it is tall.
+    fn is_canonical(&self) -> bool {
+        Orientation::all().all(|transform| {
+            let other = self.transformed(transform);
+            (self.width, &self.cells) <= (other.width, &other.cells)
+        })
+    }
+}
+
+// One tile in one orientation, with its edges read left-to-right or top-to-bottom.
+struct OrientedTile {
+    tile: usize,
+    orientation: Orientation,
+    top: Vec<bool>,
+    right: Vec<bool>,
+    bottom: Vec<bool>,
+    left: Vec<bool>,
+    // Whether any other tile could go to the right of or below this one.
+    right_matches: bool,
+    bottom_matches: bool,
+}
+
+// Fits the tiles together by brute force: fill in the grid a row at a time,
+// trying every tile in every orientation whose edges match up with the tiles
+// above and to the left, and backtracking when we get stuck.  Indexing the
+// options by edge means that in practice there's very little to try.
+//
+// We don't know how wide or tall the picture is, so try every possibility -
+// or at least every one that isn't wider than it is tall, since the others are
+// just those turned on their side.  That way the rows are short, and the tiles
+// above rule things out as early as possible.
+//
+// An edge that doesn't match any other tile has to be on the outside of the
+// picture, so a tile with one of those needs a place on the outside, one with
+// two next to each other needs a corner, and one with three needs the end of
+// a single row.  If there aren't enough of those places left for the tiles
+// still to be placed, we're stuck - which rules out most sizes straight away.
+struct Assembler<'a> {
+    tiles: &'a [Tile],
+    options: Vec<OrientedTile>,
+    tile_needs: Vec<[bool; 3]>,
+    all_options: Vec<usize>,
+    by_left_edge: HashMap<Vec<bool>, Vec<usize>>,
+    by_top_edge: HashMap<Vec<bool>, Vec<usize>>,
+}
 
-            // Slot this tile in place.
-            row[0] = first_tile_in_row.id;
-
-            // Store off information for the next row loop.
-            first_tile_id_in_previous_row = Some(first_tile_in_row.id);
-            bottom_edge_in_previous_row = first_tile_in_row.edges[2].clone();
-
-            // Prepare for the columns loop.
-            let mut previous_tile_id = first_tile_in_row.id;
-            let mut previous_tile_right_edge = first_tile_in_row.edges[1].clone();
-            let mut next_tile_id = first_tile_in_row.partners[1];
-
-            // Loop through columns, finding the right-hand partner, aligning it using our
-            // right-hand edge and slotting it into place.
-            for cell in row.iter_mut().skip(1) {
-                let next_tile_id_unwrapped = next_tile_id.unwrap();
-                *cell = next_tile_id_unwrapped;
-                let next_tile = map.get_mut(&next_tile_id_unwrapped).unwrap();
-                next_tile.align_to_right_of(previous_tile_id, &previous_tile_right_edge);
-                previous_tile_id = next_tile_id_unwrapped;
-                previous_tile_right_edge = next_tile.edges[1].clone();
-                next_tile_id = next_tile.partners[1];                
+impl<'a> Assembler<'a> {
+    fn new(tiles: &'a [Tile]) -> Self {
+        let size = tiles.first().expect("Invalid input: no tiles").data.len();
+        let mut options: Vec<OrientedTile> = Vec::new();
+        for (index, tile) in tiles.iter().enumerate() {
+            if tile.data.len() != size || tile.data.iter().any(|row| row.len() != size) {
+                panic!("Invalid input: tile {} isn't a {}x{} square", tile.id, size, size);
             }
+            for orientation in Orientation::all() {
+                let data = orientation.apply(&tile.data);
+                options.push(OrientedTile {
+                    tile: index,
+                    orientation,
+                    top: data[0].clone(),
+                    right: data.iter().map(|row| row[size - 1]).collect(),
+                    bottom: data[size - 1].clone(),
+                    left: data.iter().map(|row| row[0]).collect(),
+                    right_matches: false,
+                    bottom_matches: false,
+                });
+            }
+        }
+
+        let mut by_left_edge: HashMap<Vec<bool>, Vec<usize>> = HashMap::new();
+        let mut by_top_edge: HashMap<Vec<bool>, Vec<usize>> = HashMap::new();
+        for (index, option) in options.iter().enumerate() {
+            by_left_edge.entry(option.left.clone()).or_default().push(index);
+            by_top_edge.entry(option.top.clone()).or_default().push(index);
         }
 
-        tiles
+        // Every side of a tile is some orientation's left edge, read both ways,
+        // so that's all we need to look at to see if anything else matches it.
+        let option_tiles: Vec<usize> = options.iter().map(|option| option.tile).collect();
+        let matches = |edge: &Vec<bool>, tile: usize| by_left_edge.get(edge).is_some_and(|found| found.iter().any(|&other| option_tiles[other] != tile));
+        let flags: Vec<(bool, bool)> = options.iter().map(|option| (matches(&option.right, option.tile), matches(&option.bottom, option.tile))).collect();
+        for (option, (right_matches, bottom_matches)) in options.iter_mut().zip(flags) {
+            option.right_matches = right_matches;
+            option.bottom_matches = bottom_matches;
+        }
+        let tile_needs = options.iter().filter(|option| option.orientation == Orientation::default()).map(|option| {
+            outside_needs([&option.top, &option.right, &option.bottom, &option.left].map(|edge| !matches(edge, option.tile)))
+        }).collect();
+
+        // Try the top left in the order arrangements sort in, so that the first
+        // one we find from each family is the one we'll report.
+        let mut all_options: Vec<usize> = (0..options.len()).collect();
+        all_options.sort_by_key(|&option| (tiles[options[option].tile].id, options[option].orientation));
+        Self { tiles, options, tile_needs, all_options, by_left_edge, by_top_edge }
     }
 
-    fn construct_image(map: &HashMap<u64, Tile>, ids: &[[u64; SQUARE_SIDE]]) -> [[bool; IMAGE_SIZE]; IMAGE_SIZE] {
-        // Build the complete image by copying the non-edge data from each tile.
-        let mut image = [[false; IMAGE_SIZE]; IMAGE_SIZE];
-        for (tile_row, ids_row) in ids.iter().enumerate() {
-            for (tile_col, id) in ids_row.iter().enumerate() {
-                let tile = map.get(id).unwrap();
-                for row_in_tile in 0..STRIPPED_TILE_SIZE {
-                    for col_in_tile in 0..STRIPPED_TILE_SIZE {
-                        let row = (tile_row * STRIPPED_TILE_SIZE) + row_in_tile;
-                        let col = (tile_col * STRIPPED_TILE_SIZE) + col_in_tile;
-                        image[row][col] = tile.data[row_in_tile+1][col_in_tile+1];
-                    }
-                }
+    // Every arrangement, up to `limit` of them.
+    fn solve(&self, limit: usize) -> Vec<Assembly> {
+        let count = self.tiles.len();
+        let mut solutions: Vec<Assembly> = Vec::new();
+        for width in (1..=count).filter(|&width| count.is_multiple_of(width) && width * width <= count) {
+            let height = count / width;
+            // How many places on the outside, corners and ends there are from
+            // each cell onwards.
+            let mut room_from = vec![[0; 3]; count + 1];
+            for cell in (0..count).rev() {
+                let (row, col) = (cell / width, cell % width);
+                let needs = outside_needs([row == 0, col == width - 1, row == height - 1, col == 0]);
+                room_from[cell] = [0, 1, 2].map(|need| room_from[cell + 1][need] + needs[need] as usize);
             }
+            let mut search = Search {
+                width,
+                height,
+                corner_checks: corner_checks(width, height),
+                room_from,
+                still_needed: [0, 1, 2].map(|need| self.tile_needs.iter().filter(|needs| needs[need]).count()),
+                placed: Vec::with_capacity(count),
+                used: vec![false; count],
+                limit,
+            };
+            self.search(&mut search, &mut solutions);
+            if solutions.len() >= limit { break; }
         }
-        image
+        solutions
     }
 
-    fn find_corners(map: &HashMap<u64, Tile>) -> impl Iterator<Item = u64> + '_ {
-        // The corners are the tiles with two partners.
-        map.values().filter(|tile| tile.partners.iter().filter(|partner| partner.is_some()).count() == 2).map(|tile| tile.id)
+    fn placement(&self, option: usize) -> Placement {
+        Placement { id: self.tiles[self.options[option].tile].id, orientation: self.options[option].orientation }
     }
 
-    fn rotate(&mut self) {
-        // Rotate a the whole image clockwise.
-        let mut new_data = [[false; IMAGE_SIZE]; IMAGE_SIZE];
-        for (row, row_data) in self.data.iter().enumerate() {
-            for (col, cell) in row_data.iter().enumerate() {
-                new_data[col][IMAGE_SIZE - row - 1] = *cell;
+    fn search(&self, search: &mut Search, solutions: &mut Vec<Assembly>) {
+        let (width, height) = (search.width, search.height);
+        let cell = search.placed.len();
+        if cell == width * height {
+            let cells = search.placed.iter().map(|&option| self.placement(option)).collect();
+            let assembly = Assembly { width, cells };
+            if assembly.is_canonical() {
+                solutions.push(assembly);
             }
+            return;
+        }
+        if (0..3).any(|need| search.still_needed[need] > search.room_from[cell][need]) {
+            return;
+        }
+
+        let above = if cell >= width { Some(&self.options[search.placed[cell - width]]) } else { None };
+        let left = if !cell.is_multiple_of(width) { Some(&self.options[search.placed[cell - 1]]) } else { None };
+        let candidates: &[usize] = match (left, above) {
+            (Some(left), _) => self.by_left_edge.get(&left.right).map_or(&[], Vec::as_slice),
+            (None, Some(above)) => self.by_top_edge.get(&above.bottom).map_or(&[], Vec::as_slice),
+            (None, None) => &self.all_options,
+        };
+
+        for &candidate in candidates {
+            let option = &self.options[candidate];
+            if search.used[option.tile] { continue; }
+            if let Some(above) = above {
+                if above.bottom != option.top { continue; }
+            }
+            if (cell % width < width - 1 && !option.right_matches) || (cell / width < height - 1 && !option.bottom_matches) { continue; }
+            // If flipping/rotating the whole picture would bring this corner
+            // round to the top left and sort before what's there now, this
+            // isn't the arrangement we want to report from its family, so
+            // there's no point carrying on.
+            let placement = self.placement(candidate);
+            let top_left = if cell == 0 { placement } else { self.placement(search.placed[0]) };
+            let sorts_first = search.corner_checks.iter().filter(|(corner, _)| *corner == cell).all(|&(_, transform)| {
+                top_left <= Placement { id: placement.id, orientation: placement.orientation.then(transform) }
+            });
+            if !sorts_first { continue; }
+            let needs = self.tile_needs[option.tile];
+            search.used[option.tile] = true;
+            search.placed.push(candidate);
+            (0..3).for_each(|need| search.still_needed[need] -= needs[need] as usize);
+            self.search(search, solutions);
+            (0..3).for_each(|need| search.still_needed[need] += needs[need] as usize);
+            search.placed.pop();
+            search.used[option.tile] = false;
+            if solutions.len() >= search.limit { return; }
         }
-        self.data = new_data;
     }
+}
 
-    fn flip(&mut self) {
-        // Perform a vertical flip of this image, so that the first row
-        // becomes the last row.
-        let mut new_data = [[false; IMAGE_SIZE]; IMAGE_SIZE];
-        for (row, row_data) in self.data.iter().enumerate() {
-            new_data[IMAGE_SIZE - row - 1] = *row_data;
+// The state of the search for one size of picture.
+struct Search {
+    width: usize,
+    height: usize,
+    // Which flips/rotations of the whole picture keep its shape, and which
+    // corner they'd move to the top left.
+    corner_checks: Vec<(usize, Orientation)>,
+    // Places on the outside, corners and ends: how many there are from each
+    // cell onwards, and how many of the tiles still to be placed need one.
+    room_from: Vec<[usize; 3]>,
+    still_needed: [usize; 3],
+    placed: Vec<usize>,
+    used: Vec<bool>,
+    // Stop once we've found this many arrangements.
+    limit: usize,
+}
+
+// Given which of a tile's sides (top, right, bottom, left) have to be on the
+// outside of the picture - or which sides of a place in the picture are - does
+// it need (or is it) a place on the outside, a corner, the end of a row?
+fn outside_needs(sides: [bool; 4]) -> [bool; 3] {
+    let outside = sides.iter().filter(|&&side| side).count();
+    [outside >= 1, (0..4).any(|side| sides[side] && sides[(side + 1) % 4]), outside >= 3]
+}
+
+fn corner_checks(width: usize, height: usize) -> Vec<(usize, Orientation)> {
+    let positions: Vec<Vec<usize>> = (0..height).map(|row| (row * width..(row + 1) * width).collect()).collect();
+    Orientation::all().filter_map(|transform| {
+        let moved = transform.apply(&positions);
+        if moved.len() == height { Some((moved[0][0], transform)) } else { None }
+    }).collect()
+}
+
+struct Image {
+    data: Vec<Vec<bool>>,
+    tile_size: usize,
+}
+
+impl std::fmt::Display for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        for row in self.data.iter() {
+            for cell in row {
+                if *cell { output.push('#'); } else { output.push('.'); }
+            }
+            output.push('\n');
         }
-        self.data = new_data;
-    }    
+        write!(f, "{}", output)
+    }
+}
 
-    fn water_roughness(&mut self) -> usize {
-        self.count_filled_cells() - (self.find_and_count_sea_monsters() * SEA_MONSTER_CELLS.len())
+impl Image {
+    fn construct(tiles: &[Tile], assembly: &Assembly) -> Self {
+        // Build the complete image by copying the non-edge data from each
+        // tile, reoriented as the assembly says.
+        let tiles_by_id: HashMap<u64, &Tile> = tiles.iter().map(|tile| (tile.id, tile)).collect();
+        let tile_size = tiles[0].data.len() - 2;
+        let mut data: Vec<Vec<bool>> = Vec::new();
+        for row in assembly.cells.chunks(assembly.width) {
+            let oriented: Vec<Vec<Vec<bool>>> = row.iter().map(|cell| cell.orientation.apply(&tiles_by_id[&cell.id].data)).collect();
+            for row_in_tile in 1..=tile_size {
+                data.push(oriented.iter().flat_map(|tile| tile[row_in_tile][1..=tile_size].iter().copied()).collect());
+            }
+        }
+        Self { data, tile_size }
     }
 
     fn count_filled_cells(&self) -> usize {
         self.data.iter().flatten().filter(|cell| **cell).count()
     }
 
-    fn find_and_count_sea_monsters(&mut self) -> usize {
-        // Try every orientation until we can find at least one sea monster.
-        let mut sea_monsters = self.count_sea_monsters();
-        if sea_monsters == 0 {
-            for _ in 0..3 {
-                self.rotate();
-                sea_monsters = self.count_sea_monsters();
-                if sea_monsters != 0 { break; }
-            }
-        }
-        if sea_monsters == 0 {
-            self.flip();
-            sea_monsters = self.count_sea_monsters();
-        }
-        if sea_monsters == 0 {
-            for _ in 0..3 {
-                self.rotate();
-                sea_monsters = self.count_sea_monsters();
-                if sea_monsters != 0 { break; }
+    // Find everywhere the pattern appears, in any orientation.  Rather than
+    // reorienting the whole image, we reorient the pattern, so the positions
+    // we report are in terms of the image as assembled.
+    fn find_matches(&self, pattern: &Pattern) -> Vec<Match> {
+        let rows = self.data.len();
+        let cols = self.data.first().map_or(0, Vec::len);
+        let mut matches: Vec<Match> = Vec::new();
+
+        // A symmetrical pattern looks the same in more than one orientation -
+        // only search for each distinct version once, otherwise we'd count
+        // the same match repeatedly.
+        let mut seen: HashSet<Vec<Vec<bool>>> = HashSet::new();
+        for orientation in Orientation::all() {
+            let oriented = orientation.apply(&pattern.data);
+            if !seen.insert(oriented.clone()) { continue; }
+            let pattern_rows = oriented.len();
+            let pattern_cols = oriented.first().map_or(0, Vec::len);
+            if pattern_rows > rows || pattern_cols > cols { continue; }
+
+            let cells: Vec<(usize, usize)> = (0..pattern_rows)
+                .flat_map(|row| (0..pattern_cols).map(move |col| (row, col)))
+                .filter(|&(row, col)| oriented[row][col])
+                .collect();
+            for row in 0..=rows-pattern_rows {
+                for col in 0..=cols-pattern_cols {
+                    if cells.iter().all(|(cell_row, cell_col)| self.data[row + cell_row][col + cell_col]) {
+                        let cells = cells.iter().map(|(cell_row, cell_col)| (row + cell_row, col + cell_col)).collect();
+                        matches.push(Match { row, col, orientation, cells });
+                    }
+                }
             }
         }
-        sea_monsters
+        matches
     }
 
-    fn count_sea_monsters(&self) -> usize {
-        let mut sea_monsters = 0usize;
-        for row_index in 0..=IMAGE_SIZE-SEA_MONSTER_ROWS {
-            for col_index in 0..=IMAGE_SIZE-SEA_MONSTER_COLS {
-                if self.sea_monster_at(row_index, col_index) { sea_monsters += 1; }
-            }
+    // How many set cells aren't part of any match.  Matches may overlap, so we
+    // can't just multiply up the number of cells in the pattern.
+    fn roughness(&self, matches: &[&Match]) -> usize {
+        let covered: HashSet<(usize, usize)> = matches.iter().flat_map(|found| found.cells.iter().copied()).collect();
+        self.count_filled_cells() - covered.len()
+    }
+}
+
+// The picture only has one right way up, so only the orientation with the most
+// matches counts towards the roughness - anything that turns up in other
+// orientations is just noise that happens to look like the pattern.
+fn in_best_orientation(matches: &[Match]) -> Vec<&Match> {
+    let mut counts: Vec<(Orientation, usize)> = Vec::new();
+    for found in matches {
+        match counts.iter_mut().find(|(orientation, _)| *orientation == found.orientation) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((found.orientation, 1)),
         }
-        sea_monsters
     }
+    // On a tie, the first orientation searched wins.
+    let best = counts.iter().rev().max_by_key(|(_, count)| *count).map(|(orientation, _)| *orientation);
+    matches.iter().filter(|found| Some(found.orientation) == best).collect()
+}
+
+// Something to search the image for.  The pattern needn't be rectangular - short
+// lines are treated as if they were padded with spaces.
+struct Pattern {
+    name: String,
+    data: Vec<Vec<bool>>,
+}
 
-    fn sea_monster_at(&self, row_index: usize, col_index: usize) -> bool {
-        SEA_MONSTER_CELLS.iter().all(|(row, col)| self.data[row + row_index][col + col_index])
+impl Pattern {
+    fn parse<'a>(name: &str, lines: impl Iterator<Item = &'a str>) -> Self {
+        let mut data: Vec<Vec<bool>> = lines.map(|line| line.chars().map(|c| c == '#').collect()).collect();
+        // Blank rows and columns round the outside would only stop the pattern
+        // being found near the edges of the image, so trim them off.
+        while data.last().is_some_and(|row| !row.contains(&true)) { data.pop(); }
+        let blank_rows = data.iter().take_while(|row| !row.contains(&true)).count();
+        data.drain(..blank_rows);
+        let first_col = data.iter().filter_map(|row| row.iter().position(|cell| *cell)).min().unwrap_or(0);
+        let cols = data.iter().filter_map(|row| row.iter().rposition(|cell| *cell)).max().map_or(0, |last| last + 1);
+        for row in data.iter_mut() {
+            row.resize(cols, false);
+            row.drain(..first_col);
+        }
+        if !data.iter().flatten().any(|cell| *cell) {
+            panic!("Pattern {} doesn't contain any '#'s", name);
+        }
+        Self { name: name.to_string(), data }
     }
 }
 
+struct Match {
+    row: usize,
+    col: usize,
+    orientation: Orientation,
+    cells: Vec<(usize, usize)>,
+}
+
 struct Tile {
     id: u64,
-    data: [[bool; TILE_SIZE]; TILE_SIZE],
-    edges: [String; 4],
-    partners: [Option<u64>; 4],
+    data: Vec<Vec<bool>>,
 }
 
 impl Tile {
     fn parse(input_lines: &[String]) -> Self {
-        let id = input_lines[0][5..9].parse::<u64>().expect("Invalid input: can't read tile ID");
+        let id = input_lines[0].strip_prefix("Tile ")
+            .and_then(|line| line.strip_suffix(':'))
+            .and_then(|id| id.parse::<u64>().ok())
+            .expect("Invalid input: can't read tile ID");
+        let data = input_lines[1..].iter().map(|line| line.chars().map(|c| c == '#').collect()).collect();
+        Self { id, data }
+    }
+}
 
-        // Store off all the tile cells in a 2D array.
-        let mut data: [[bool; TILE_SIZE]; TILE_SIZE] = [[false; TILE_SIZE]; TILE_SIZE];
-        for (row, line) in input_lines[1..=TILE_SIZE].iter().enumerate() {
-            for (col, c) in line.chars().enumerate() {
-                data[row][col] = c == '#';
-            }
-        }
+// Exporting the finished picture.  In the raster formats each cell of the image
+// becomes an EXPORT_SCALE x EXPORT_SCALE block of pixels, so there's room to
+// draw the seams between tiles.
+const EXPORT_SCALE: usize = 4;
+const WATER_COLOUR: [u8; 3] = [0xe8, 0xf4, 0xff];
+const ROUGH_COLOUR: [u8; 3] = [0x10, 0x3c, 0x78];
+const HIGHLIGHT_COLOUR: [u8; 3] = [0xe0, 0x30, 0x20];
+const SEAM_COLOUR: [u8; 3] = [0xa0, 0xa0, 0xa0];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pixel {
+    Water,
+    Rough,
+    Highlight { edge: bool },
+    Seam,
+}
 
-        // Construct the edges - top, right, bottom, left. Edges are parsed in a clockwise
-        // manner, i.e. the top is read left-to-right while the bottom is read right-to-left.
-        let mut edges = [String::with_capacity(TILE_SIZE), String::with_capacity(TILE_SIZE), String::with_capacity(TILE_SIZE), String::with_capacity(TILE_SIZE)];
-        let mut build_edge = |edge: usize, row: usize, col: usize| {
-            let c: char = if data[row][col] { '#' } else { '.' };
-            edges[edge].push(c);
+impl Image {
+    fn export(&self, file: &str, assembly: &Assembly, highlights: &HashSet<(usize, usize)>, seams: bool) {
+        let extension = std::path::Path::new(file).extension().and_then(std::ffi::OsStr::to_str).unwrap_or("");
+        let contents: Vec<u8> = match extension.to_lowercase().as_str() {
+            "pbm" => self.to_pbm(highlights, seams),
+            "ppm" => self.to_ppm(highlights, seams),
+            "png" => self.to_png(highlights, seams),
+            "svg" => self.to_svg(assembly, highlights, seams).into_bytes(),
+            _ => panic!("Don't know how to export to {} - use .pbm, .ppm, .png or .svg", file),
         };
-        for index in 0..TILE_SIZE {
-            build_edge(0, 0, index);
-            build_edge(1, index, TILE_SIZE - 1);
-            build_edge(2, TILE_SIZE - 1, TILE_SIZE - index - 1);
-            build_edge(3, TILE_SIZE - index - 1, 0);
-        }
-
-        Self { id, data, edges, partners: [None; 4] }
+        std::fs::write(file, contents).expect("Can't write export file");
     }
 
-    fn edge_matched(&mut self, edge_str: &str, partner_id: u64) {
-        // Record that we've matched up one of our edges to a partner.
-        for index in 0..4 {
-            if self.edges[index].eq(edge_str) {
-                self.partners[index] = Some(partner_id);
-                break;
+    fn rasterise(&self, highlights: &HashSet<(usize, usize)>, seams: bool) -> Vec<Vec<Pixel>> {
+        let cols = self.data.first().map_or(0, Vec::len);
+        (0..self.data.len() * EXPORT_SCALE).map(|y| (0..cols * EXPORT_SCALE).map(|x| {
+            let (row, col) = (y / EXPORT_SCALE, x / EXPORT_SCALE);
+            let (y_in_cell, x_in_cell) = (y % EXPORT_SCALE, x % EXPORT_SCALE);
+            let on_seam = (row.is_multiple_of(self.tile_size) && y_in_cell == 0 && row > 0) ||
+                          (col.is_multiple_of(self.tile_size) && x_in_cell == 0 && col > 0);
+            if seams && on_seam {
+                Pixel::Seam
+            } else if highlights.contains(&(row, col)) {
+                let edge = y_in_cell == 0 || x_in_cell == 0 || y_in_cell == EXPORT_SCALE - 1 || x_in_cell == EXPORT_SCALE - 1;
+                Pixel::Highlight { edge }
+            } else if self.data[row][col] {
+                Pixel::Rough
+            } else {
+                Pixel::Water
             }
-        }
+        }).collect()).collect()
     }
 
-    // Rotate this tile (must be a corner tile) so that it's in the top left.
-    fn align_to_top_left_corner(&mut self) {
-        let times_to_rotate = if self.partners[0].is_some() {
-            if self.partners[1].is_some() { 1 } else { 2 }
-        } else if self.partners[3].is_some() { 3 } else { 0 };
-        self.rotate(times_to_rotate);
-    }
-
-    // Rotate/flip this tile such that the specified tile is to its left and the edge matches up.
-    fn align_to_right_of(&mut self, tile_to_left: u64, edge: &str) {
-        let times_to_rotate = 3 - self.find_partner_direction(tile_to_left);
-        self.rotate(times_to_rotate);
-        if !self.edge_matches(3, edge) { self.flip_vertical(); }
+    // PBM is black and white only, so highlighted cells are drawn as hollow
+    // squares and seams as dotted lines.
+    fn to_pbm(&self, highlights: &HashSet<(usize, usize)>, seams: bool) -> Vec<u8> {
+        let pixels = self.rasterise(highlights, seams);
+        let mut output = format!("P1\n{} {}\n", pixels.first().map_or(0, Vec::len), pixels.len());
+        for (y, row) in pixels.iter().enumerate() {
+            let bits: Vec<&str> = row.iter().enumerate().map(|(x, pixel)| match pixel {
+                Pixel::Water | Pixel::Highlight { edge: false } => "0",
+                Pixel::Rough | Pixel::Highlight { edge: true } => "1",
+                Pixel::Seam => if (x + y) % 2 == 0 { "1" } else { "0" },
+            }).collect();
+            output.push_str(&bits.join(" "));
+            output.push('\n');
+        }
+        output.into_bytes()
     }
 
-    // Rotate this tile such that the specified tile is above it.
-    fn align_to_below(&mut self, tile_above: u64, edge: &str) {
-        let times_to_rotate = (4 - self.find_partner_direction(tile_above)) % 4;
-        self.rotate(times_to_rotate);
-        if !self.edge_matches(0, edge) { self.flip_horizontal(); }
+    fn to_rgb(&self, highlights: &HashSet<(usize, usize)>, seams: bool) -> (usize, usize, Vec<u8>) {
+        let pixels = self.rasterise(highlights, seams);
+        let bytes = pixels.iter().flatten().flat_map(|pixel| match pixel {
+            Pixel::Water => WATER_COLOUR,
+            Pixel::Rough => ROUGH_COLOUR,
+            Pixel::Highlight { .. } => HIGHLIGHT_COLOUR,
+            Pixel::Seam => SEAM_COLOUR,
+        }.to_vec()).collect();
+        (pixels.first().map_or(0, Vec::len), pixels.len(), bytes)
     }
 
-    fn find_partner_direction(&self, partner_id: u64) -> usize {
-        // Which of this tile's partners is the specified tile ID? Returns an index into
-        // the partners/edges arrays.
-        self.partners.iter().enumerate().find(|(_, partner)| matches!(**partner, Some(tile_id) if tile_id == partner_id)).expect("Mismatching partners").0
+    fn to_ppm(&self, highlights: &HashSet<(usize, usize)>, seams: bool) -> Vec<u8> {
+        let (width, height, bytes) = self.to_rgb(highlights, seams);
+        let mut output = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        output.extend(bytes);
+        output
     }
 
-    fn rotate(&mut self, times: usize) {
-        // Rotate a tile clockwise, X times. Yeah, we could do this much more efficiently by
-        // applying different logic depending on how many times we're rotating, but CBA.
-        for _ in 0..times {
-            let mut new_data = [[false; TILE_SIZE]; TILE_SIZE];
-            for (row, row_data) in self.data.iter().enumerate() {
-                for (col, cell) in row_data.iter().enumerate() {
-                    new_data[col][TILE_SIZE - row - 1] = *cell;
-                }
-            }
-            self.data = new_data;
-            self.edges.rotate_right(1);
-            self.partners.rotate_right(1);      
-        }
+    fn to_png(&self, highlights: &HashSet<(usize, usize)>, seams: bool) -> Vec<u8> {
+        let (width, height, bytes) = self.to_rgb(highlights, seams);
+        let mut output: Vec<u8> = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().expect("Can't encode PNG");
+        writer.write_image_data(&bytes).expect("Can't encode PNG");
+        writer.finish().expect("Can't encode PNG");
+        output
     }
 
-    fn edge_matches(&self, edge_index: usize, other_edge: &str) -> bool {
-        // Does one of our edges match another edge? This function is used
-        // during alignment, not during initial matching, so we specifically
-        // require the edge of one tile to be the reverse of the matching
-        // edge. If they match without reversing, the tile needs to be
-        // flipped.
-        self.edges[edge_index].chars().rev().eq(other_edge.chars())
-    }
-
-    fn flip_vertical(&mut self) {
-        // Perform a vertical flip of this tile, so that the first row
-        // becomes the last row.
-        let mut new_data = [[false; TILE_SIZE]; TILE_SIZE];
-        for (row, row_data) in self.data.iter().enumerate() {
-            new_data[TILE_SIZE - row - 1] = *row_data;
-        }
-        self.data = new_data;
-        let new_edge_0: String = self.edges[2].chars().rev().collect();
-        self.edges[2] = self.edges[0].chars().rev().collect();
-        self.edges[0] = new_edge_0;
-        self.edges[1] = self.edges[1].chars().rev().collect();
-        self.edges[3] = self.edges[3].chars().rev().collect();
-        self.partners.swap(0, 2);
-    }
-
-    fn flip_horizontal(&mut self) {   
-        // Perform a horizontal flip of this tile, so that the first column
-        // becomes the last column.     
-        let mut new_data = [[false; TILE_SIZE]; TILE_SIZE];
+    // The SVG works in units of one image cell, and labels every tile with its
+    // ID and orientation.
+    fn to_svg(&self, assembly: &Assembly, highlights: &HashSet<(usize, usize)>, seams: bool) -> String {
+        let rows = self.data.len();
+        let cols = self.data.first().map_or(0, Vec::len);
+        let colour = |rgb: [u8; 3]| format!("#{}", hex::encode(rgb));
+        let mut output: Vec<u8> = Vec::new();
+        writeln!(output, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#, cols * 8, rows * 8, cols, rows).unwrap();
+        writeln!(output, r#"<rect width="{}" height="{}" fill="{}"/>"#, cols, rows, colour(WATER_COLOUR)).unwrap();
         for (row, row_data) in self.data.iter().enumerate() {
             for (col, cell) in row_data.iter().enumerate() {
-                new_data[row][TILE_SIZE - col - 1] = *cell;
+                let fill = if highlights.contains(&(row, col)) { HIGHLIGHT_COLOUR } else if *cell { ROUGH_COLOUR } else { continue };
+                writeln!(output, r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#, col, row, colour(fill)).unwrap();
             }
         }
-        self.data = new_data;
-        let new_edge_1: String = self.edges[3].chars().rev().collect();
-        self.edges[3] = self.edges[1].chars().rev().collect();
-        self.edges[1] = new_edge_1;
-        self.edges[0] = self.edges[0].chars().rev().collect();
-        self.edges[2] = self.edges[2].chars().rev().collect();
-        self.partners.swap(1, 3);
-    }
-}
\ No newline at end of file
+
+        let stroke_width = if seams { 0.2 } else { 0.05 };
+        let font_size = self.tile_size as f64 / 5.0;
+        for (index, cell) in assembly.cells.iter().enumerate() {
+            let x = (index % assembly.width) * self.tile_size;
+            let y = (index / assembly.width) * self.tile_size;
+            let centre = self.tile_size as f64 / 2.0;
+            writeln!(output, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+                     x, y, self.tile_size, self.tile_size, colour(SEAM_COLOUR), stroke_width).unwrap();
+            writeln!(output, r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" fill="black" fill-opacity="0.7">{}</text>"#,
+                     x as f64 + centre, y as f64 + centre, font_size, cell.id).unwrap();
+            writeln!(output, r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" fill="black" fill-opacity="0.7">{}</text>"#,
+                     x as f64 + centre, y as f64 + centre + font_size, font_size, cell.orientation).unwrap();
+        }
+        writeln!(output, "</svg>").unwrap();
+        String::from_utf8(output).unwrap()
+    }
+}
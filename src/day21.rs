@@ -1,9 +1,14 @@
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use crate::assignment::{self, Deduction, Solution};
+use crate::utils;
+
+// Run with --report to see what we worked out about every ingredient and why,
+// or --report=json to get the same thing as JSON.
 
 pub fn day21(input_lines: &[String]) -> (u64, u64) {
-    let mut allergens: HashSet<String> = HashSet::new();
-    let mut ingredients: HashMap<String, Ingredient> = HashMap::new();
-    let mut foods: Vec<Food> = input_lines.iter().map(|line| Food::parse(line)).collect();
+    let foods: Vec<Food> = input_lines.iter().map(|line| Food::parse(line)).collect();
+    let mut allergens: BTreeSet<String> = BTreeSet::new();
+    let mut ingredients: BTreeMap<String, Ingredient> = BTreeMap::new();
     for (index, food) in foods.iter().enumerate() {
         allergens.extend(food.allergens.iter().cloned());
         for ingredient_name in &food.ingredients {
@@ -11,88 +16,176 @@ pub fn day21(input_lines: &[String]) -> (u64, u64) {
             ingredient.appears_in.insert(index);
         }
     }
-    // To begin with, assume any ingredient could contain any allergen.
-    for ingredient in ingredients.values_mut() {
-        ingredient.candidate_allergens.extend(allergens.iter().cloned());
-    }
-    // For each ingredient, strike out any allergens contained in foods that don't contain
-    // this ingredient.
+    // To begin with, assume any ingredient could contain any allergen - but strike
+    // out any allergens contained in foods that don't contain this ingredient, and
+    // remember which foods those were.
     for ingredient in ingredients.values_mut() {
-        let foods_not_containing_ingredient: Vec<usize> = (0..foods.len()).filter(|index| !ingredient.appears_in.contains(index)).collect();
-        for index in foods_not_containing_ingredient {
-            ingredient.candidate_allergens.retain(|allergen| !foods[index].allergens.contains(allergen));
+        for allergen in &allergens {
+            let ruled_out_by: Vec<usize> = (0..foods.len()).filter(|index| {
+                !ingredient.appears_in.contains(index) && foods[*index].allergens.contains(allergen)
+            }).collect();
+            if ruled_out_by.is_empty() {
+                ingredient.candidate_allergens.insert(allergen.clone());
+            } else {
+                ingredient.ruled_out.insert(allergen.clone(), ruled_out_by);
+            }
         }
     }
-    // Now, identify safe ingredients - get the part 1 answer, and then forget about them.
-    let ingredients_with_no_allergens: Vec<String> = ingredients.values().filter_map(|ingredient| {
-        if ingredient.candidate_allergens.is_empty() {
-            Some(ingredient.name.clone())
-        } else {
-            None
-        }
+    // Now, identify safe ingredients, and count how many times they appear.
+    let part1: u64 = ingredients.values().filter(|ingredient| ingredient.is_safe()).map(|ingredient| ingredient.appears_in.len() as u64).sum();
+
+    // We now have some unsafe ingredients, each of which has a list of allergens
+    // it might contain. Work out which is which.
+    let allergen_names: Vec<&String> = allergens.iter().collect();
+    let unsafe_names: Vec<&String> = ingredients.values().filter(|ingredient| !ingredient.is_safe()).map(|ingredient| &ingredient.name).collect();
+    let candidates: Vec<BTreeSet<usize>> = allergen_names.iter().map(|&allergen| {
+        (0..unsafe_names.len()).filter(|&index| ingredients[unsafe_names[index]].candidate_allergens.contains(allergen)).collect()
     }).collect();
-    let mut part1 = 0u64;
-    for ingredient in ingredients_with_no_allergens {
-        for food in foods.iter_mut() {
-            part1 += 1;
-            food.ingredients.remove(&ingredient);
-            ingredients.remove(&ingredient);
+    let (solution, deductions) = assignment::solve_with_deductions(candidates, unsafe_names.len());
+    let matches = match solution {
+        Solution::Unique(matches) => matches,
+        Solution::Multiple(matches, other_matches) => {
+            let describe = |matches: &[usize]| matches.iter().map(|&index| unsafe_names[index].as_str()).collect::<Vec<&str>>().join(",");
+            println!("The allergens could be in {} or {} (and maybe more) - picking the first", describe(&matches), describe(&other_matches));
+            matches
+        },
+        Solution::Impossible => panic!("Invalid input: there's no way to match the allergens to ingredients"),
+    };
+
+    let dangerous_ingredients = matches.iter().map(|&index| unsafe_names[index].clone()).collect::<Vec<String>>().join(",");
+    println!("Part 2: {}", dangerous_ingredients);
+
+    if utils::option_set("report") {
+        let report = Report { foods: &foods, ingredients: &ingredients, allergen_names: &allergen_names, unsafe_names: &unsafe_names, matches: &matches, deductions: &deductions };
+        if utils::option_value("report").as_deref() == Some("json") {
+            println!("{}", report.to_json());
+        } else {
+            print!("{}", report);
         }
     }
 
-    // We now have only unsafe ingredients. Start by deriving two pieces of information:
-    // -  If any food contains as many allergens as unsafe ingredients, each of those
-    //    ingredients MUST contain one of those allergens - so those ingredients cannot
-    //    contain any other allergens.
-    //    1, 2 (contains A, B)
-    //    2, 3 (contains C)
-    //    Because of the first food, we can eliminate 2 from the second food.
-    //    (Logically this also tells us that those allergens cannot be contained in any
-    //    other ingredients, but we don't bother tracking that in this implementation.)
-    // -  If, after excluding such impossibilities, a food contains only one allergen
-    //    and one candidate ingredient, it's a match. So, in the example above, we can
-    //    match 3 = C.
-    let mut matched_ingredients: HashSet<String> = HashSet::with_capacity(allergens.len());
-    let mut matches: HashMap<String, String> = HashMap::with_capacity(allergens.len());
-    while matches.len() < allergens.len() {
-        for food in foods.iter_mut() {
-            food.ingredients.retain(|ingredient_name| !matched_ingredients.contains(ingredient_name));
-            food.allergens.retain(|allergen_name| !matches.contains_key(allergen_name));
-            if food.ingredients.len() == food.allergens.len() {
-                for ingredient in ingredients.values_mut() {
-                    if !food.ingredients.contains(&ingredient.name) {
-                        // This ingredient is no longer a candidate for any of this food's allergens.
-                        ingredient.candidate_allergens.retain(|allergen_name| !food.allergens.contains(allergen_name));
-                    } else {
-                        // This ingredient is no longer a candidate for any allergens other than those
-                        // in this food.
-                        ingredient.candidate_allergens.retain(|allergen_name| food.allergens.contains(allergen_name));
-                    }
-                }
-            }
+    (part1,0)
+}
+
+// Everything we worked out about every ingredient, and how.
+struct Report<'a> {
+    foods: &'a [Food],
+    ingredients: &'a BTreeMap<String, Ingredient>,
+    allergen_names: &'a [&'a String],
+    unsafe_names: &'a [&'a String],
+    matches: &'a [usize],
+    deductions: &'a [Deduction],
+}
+
+// One step of reasoning about an ingredient, and the foods (numbered from 1, as
+// lines in the input) that it relied on.
+struct Reason {
+    text: String,
+    foods: Vec<usize>,
+}
+
+impl Reason {
+    fn new(text: String) -> Self {
+        Self { text, foods: Vec::new() }
+    }
+}
+
+impl Report<'_> {
+    fn allergen_index(&self, ingredient: &Ingredient) -> Option<usize> {
+        let unsafe_index = self.unsafe_names.iter().position(|&name| *name == ingredient.name)?;
+        self.matches.iter().position(|&index| index == unsafe_index)
+    }
+
+    fn reasons(&self, ingredient: &Ingredient) -> Vec<Reason> {
+        let name = &ingredient.name;
+        let mut reasons: Vec<Reason> = ingredient.ruled_out.iter().map(|(allergen, foods)| Reason {
+            text: format!("{} can't contain {}: some foods contain {} but not {}", name, allergen, allergen, name),
+            foods: foods.iter().map(|index| index + 1).collect(),
+        }).collect();
+        if ingredient.is_safe() {
+            reasons.push(Reason::new(format!("{} can't contain any allergens, so it's safe", name)));
+            return reasons;
         }
-        for ingredient in ingredients.values_mut() {
-            ingredient.candidate_allergens.retain(|allergen_name| !matches.contains_key(allergen_name));
-            if ingredient.candidate_allergens.len() == 1 {
-                // This ingredient now has a definite match.
-                matched_ingredients.insert(ingredient.name.clone());
-                matches.insert(ingredient.candidate_allergens.iter().next().unwrap().clone(), ingredient.name.clone());
-            }
+
+        let unsafe_index = self.unsafe_names.iter().position(|&other| other == name).unwrap();
+        let allergen_index = self.allergen_index(ingredient);
+        if let Some(allergen_index) = allergen_index {
+            let allergen = self.allergen_names[allergen_index];
+            let candidates: Vec<&str> = self.unsafe_names.iter().filter(|&&other| self.ingredients[other].candidate_allergens.contains(allergen)).map(|other| other.as_str()).collect();
+            reasons.push(Reason {
+                text: format!("{} could only be in {}: the ingredients in every food that contains it", allergen, candidates.join(" or ")),
+                foods: (0..self.foods.len()).filter(|&index| self.foods[index].allergens.contains(allergen)).map(|index| index + 1).collect(),
+            });
         }
+
+        // Now the deductions that involved either this ingredient or its allergen.
+        let involves = |item: usize, choice: usize| choice == unsafe_index || Some(item) == allergen_index;
+        for deduction in self.deductions {
+            let text = match *deduction {
+                Deduction::Excluded { item, choice, by } if involves(item, choice) => {
+                    format!("{} can't contain {}: it must contain {}", self.unsafe_names[choice], self.allergen_names[item], self.allergen_names[by])
+                },
+                Deduction::OnlyHolder { item, choice } if involves(item, choice) => {
+                    format!("{} is the only ingredient left that could contain {}", self.unsafe_names[choice], self.allergen_names[item])
+                },
+                Deduction::Guessed { item, choice } if involves(item, choice) => {
+                    format!("out of deductions - guessed that {} contains {}", self.unsafe_names[choice], self.allergen_names[item])
+                },
+                _ => continue,
+            };
+            reasons.push(Reason::new(text));
+        }
+
+        match allergen_index {
+            Some(allergen_index) => reasons.push(Reason::new(format!("so {} contains {}", name, self.allergen_names[allergen_index]))),
+            None => reasons.push(Reason::new(format!("every allergen is in some other ingredient, so {} is safe after all", name))),
+        }
+        reasons
     }
 
-    let mut allergen_names: Vec<&String> = allergens.iter().collect();
-    allergen_names.sort();
-    let dangerous_ingredients = allergen_names.iter().map(|&name| matches.get(name).unwrap().clone()).collect::<Vec<String>>().join(",");
-    println!("Part 2: {}", dangerous_ingredients);
+    fn to_json(&self) -> String {
+        let ingredients: Vec<serde_json::Value> = self.ingredients.values().map(|ingredient| {
+            let reasons: Vec<serde_json::Value> = self.reasons(ingredient).into_iter().map(|reason| {
+                serde_json::json!({ "reason": reason.text, "foods": reason.foods })
+            }).collect();
+            serde_json::json!({
+                "name": ingredient.name,
+                "appearances": ingredient.appears_in.len(),
+                "allergen": self.allergen_index(ingredient).map(|index| self.allergen_names[index]),
+                "deductions": reasons,
+            })
+        }).collect();
+        serde_json::to_string_pretty(&serde_json::json!({ "ingredients": ingredients })).unwrap()
+    }
+}
 
-    (part1,0)
+impl std::fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for ingredient in self.ingredients.values() {
+            let times = if ingredient.appears_in.len() == 1 { "time" } else { "times" };
+            match self.allergen_index(ingredient) {
+                Some(index) => writeln!(f, "{}: contains {} (appears {} {})", ingredient.name, self.allergen_names[index], ingredient.appears_in.len(), times)?,
+                None => writeln!(f, "{}: safe (appears {} {})", ingredient.name, ingredient.appears_in.len(), times)?,
+            }
+            for reason in self.reasons(ingredient) {
+                if reason.foods.is_empty() {
+                    writeln!(f, "  - {}", reason.text)?;
+                } else {
+                    let foods: Vec<String> = reason.foods.iter().map(ToString::to_string).collect();
+                    let label = if foods.len() == 1 { "food" } else { "foods" };
+                    writeln!(f, "  - {} ({} {})", reason.text, label, foods.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 struct Ingredient {
     name: String,
     appears_in: HashSet<usize>,
     candidate_allergens: HashSet<String>,
+    ruled_out: BTreeMap<String, Vec<usize>>,
 }
 
 impl Ingredient {
@@ -101,8 +194,13 @@ impl Ingredient {
             name,
             appears_in: HashSet::new(),
             candidate_allergens: HashSet::new(),
+            ruled_out: BTreeMap::new(),
         }
     }
+
+    fn is_safe(&self) -> bool {
+        self.candidate_allergens.is_empty()
+    }
 }
 
 struct Food {
@@ -121,4 +219,4 @@ impl Food {
         }).collect();
         Self { ingredients, allergens }
     }
-}
\ No newline at end of file
+}
@@ -1,102 +1,334 @@
-use std::collections::{VecDeque, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::Entry;
+use crate::utils;
+
+// Recursive Combat gets slow if you remember every state you've seen by storing
+// full copies of both decks, so instead each deck keeps a rolling hash of its
+// contents that's updated in O(1) as cards come and go, and we look states up by
+// their hashes.  Cards only ever leave the top of a deck and join the bottom, so
+// if a deck never actually throws cards away, every earlier state of it is still
+// there as a stretch of its card list - a state can be remembered as where that
+// stretch starts and ends, and a hash match checked against the real cards.  On
+// top of that:
+// -  Sub-game results are cached, since the same sub-games crop up repeatedly.
+// -  If player 1 holds the highest card in a sub-game, and it's too high to ever
+//    trigger a further sub-game, player 1 can never lose it - so either player 1
+//    wins outright or the game repeats, which player 1 also wins.  Either way we
+//    don't need to play it.
+//
+// Run with --replay=<file> to save a record of every round of every game (part 2
+// only) as JSON lines, followed by a line of statistics about the whole thing.
+// Both shortcuts are turned off while recording, so that every sub-game really
+// is played and shows up in the record - which makes it a lot slower.
 
 pub fn day22(input_lines: &[String]) -> (u64, u64) {
     let mut input_iter = input_lines.iter();
-    let mut player1 = parse_deck(&mut input_iter);
-    let mut player1_clone = player1.clone();
-    let mut player2 = parse_deck(&mut input_iter);
-    let mut player2_clone = player2.clone();
-    play_game(&mut player1, &mut player2, false);
-    let part1 = winning_score(&player1, &player2);
-    play_game(&mut player1_clone, &mut player2_clone, true);
-    let part2 = winning_score(&player1_clone, &player2_clone);
+    let player1 = parse_deck(&mut input_iter);
+    let player2 = parse_deck(&mut input_iter);
+    let part1 = Combat::new(false).play(&player1, &player2);
+    let mut recursive_combat = Combat::new(true);
+    let replay_file = utils::option_value("replay");
+    if replay_file.is_some() {
+        recursive_combat.replay = Some(Replay::default());
+    }
+    let part2 = recursive_combat.play(&player1, &player2);
+    if let (Some(file), Some(replay)) = (replay_file, recursive_combat.replay) {
+        replay.save(&file);
+    }
     (part1,part2)
 }
 
-fn parse_deck<'a>(input_lines: &mut impl Iterator<Item = &'a String>) -> VecDeque<u64> {
-    let mut deck: VecDeque<u64> = VecDeque::new();
+fn parse_deck<'a>(input_lines: &mut impl Iterator<Item = &'a String>) -> Vec<u64> {
+    let mut deck: Vec<u64> = Vec::new();
     input_lines.next(); // Skip over the player name
     for line in input_lines {
         if line.is_empty() { break; }
-        deck.push_back(line.parse::<u64>().expect("Invalid input"));
+        deck.push(line.parse::<u64>().expect("Invalid input"));
     }
     deck
 }
 
+#[derive(Clone, Copy)]
 enum Winner {
     Player1,
     Player2,
 }
 
-#[derive(PartialEq,Eq,Hash)]
-struct GameState {
-    player1: VecDeque<u64>,
-    player2: VecDeque<u64>,
+impl Winner {
+    fn number(self) -> u64 {
+        match self {
+            Winner::Player1 => 1,
+            Winner::Player2 => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Reason {
+    HigherCard,
+    SubGame,
+    Repeat,
+}
+
+impl Reason {
+    fn name(self) -> &'static str {
+        match self {
+            Reason::HigherCard => "higher card",
+            Reason::SubGame => "sub-game",
+            Reason::Repeat => "repeat",
+        }
+    }
 }
 
-// static mut GAME_DEPTH: u64 = 0;
+// Every round of every game played, in the order they were played, plus enough
+// to summarise it all at the end.
+#[derive(Default)]
+struct Replay {
+    lines: Vec<String>,
+    games: u64,
+    games_per_depth: BTreeMap<usize, u64>,
+    longest_game: Option<(u64, usize, u64)>, // (game, depth, rounds)
+    repeats: u64,
+}
 
-fn play_game(player1: &mut VecDeque<u64>, player2: &mut VecDeque<u64>, recursive: bool) -> Winner {
-    // unsafe { GAME_DEPTH += 1; }
+impl Replay {
+    fn start_game(&mut self, depth: usize) -> u64 {
+        self.games += 1;
+        *self.games_per_depth.entry(depth).or_insert(0) += 1;
+        self.games
+    }
 
-    let mut seen_states: HashSet<GameState> = HashSet::new();
-    while !player1.is_empty() && !player2.is_empty() {
-        let state = GameState { player1: player1.clone(), player2: player2.clone() };
-        if seen_states.contains(&state) {
-            return Winner::Player1;
-        } else {
-            seen_states.insert(state);
+    fn round(&mut self, game: u64, depth: usize, round: u64, cards: Option<(u64, u64)>, winner: Winner, reason: Reason) {
+        if let Reason::Repeat = reason {
+            self.repeats += 1;
         }
+        let line = serde_json::json!({
+            "game": game,
+            "depth": depth,
+            "round": round,
+            "player1_card": cards.map(|cards| cards.0),
+            "player2_card": cards.map(|cards| cards.1),
+            "winner": winner.number(),
+            "reason": reason.name(),
+        });
+        self.lines.push(line.to_string());
+    }
 
-        play_round(player1, player2, recursive)
+    fn end_game(&mut self, game: u64, depth: usize, rounds: u64) {
+        if self.longest_game.is_none_or(|(_, _, longest)| rounds > longest) {
+            self.longest_game = Some((game, depth, rounds));
+        }
     }
 
-    // unsafe {
-    //     for _ in 0..GAME_DEPTH { print!("--"); }
-    //     println!("{}", seen_states.len());
-    //     GAME_DEPTH -= 1;
-    // }
+    fn save(mut self, file: &str) {
+        let games_per_depth: BTreeMap<String, u64> = self.games_per_depth.iter().map(|(depth, games)| (depth.to_string(), *games)).collect();
+        let summary = serde_json::json!({
+            "summary": {
+                "games": self.games,
+                "games_per_depth": games_per_depth,
+                "longest_game": self.longest_game.map(|(game, depth, rounds)| serde_json::json!({ "game": game, "depth": depth, "rounds": rounds })),
+                "repeats": self.repeats,
+            }
+        });
+        self.lines.push(summary.to_string());
+        self.lines.push(String::new());
+        std::fs::write(file, self.lines.join("\n")).expect("Can't write replay file");
+    }
+}
+
+// The hash is the deck read as a number in base HASH_BASE (wrapping at 2^64),
+// with the top card as the most significant digit.  Adding a card to the bottom
+// multiplies by the base and adds the card; taking one off the top subtracts it
+// times the top digit's place value.  Keeping that place value up to date needs
+// the base's multiplicative inverse, which exists because the base is odd.
+const HASH_BASE: u64 = 0x9e37_79b9_7f4a_7c15;
+lazy_static! {
+    static ref HASH_BASE_INVERSE: u64 = {
+        // Newton's method - each step doubles the number of correct bits.
+        let mut inverse = HASH_BASE;
+        for _ in 0..6 {
+            inverse = inverse.wrapping_mul(2u64.wrapping_sub(HASH_BASE.wrapping_mul(inverse)));
+        }
+        inverse
+    };
+}
 
-    if player2.is_empty() { Winner::Player1 } else { Winner::Player2 }
+// The deck is everything in `history` from `top` on.  Cards that have been
+// played are left where they are, so earlier states can be looked back at.
+struct Deck {
+    history: Vec<u64>,
+    top: usize,
+    hash: u64,
+    top_place_value: u64,
 }
 
-fn play_round(player1: &mut VecDeque<u64>, player2: &mut VecDeque<u64>, recursive: bool) {
-    let player1_card = player1.pop_front().unwrap();
-    let player2_card = player2.pop_front().unwrap();
-    match determine_winner(player1, &player1_card, player2, &player2_card, recursive) {
-        Winner::Player1 => {
-            player1.push_back(player1_card);
-            player1.push_back(player2_card);
-        },
-        Winner::Player2 => {
-            player2.push_back(player2_card);
-            player2.push_back(player1_card);
+// Where a past state of a deck sits in its history.  There can be a lot of
+// these, so they're kept small.
+type Span = (u32, u32);
+
+impl Deck {
+    fn new(cards: &[u64]) -> Self {
+        let mut deck = Self { history: Vec::with_capacity(cards.len()), top: 0, hash: 0, top_place_value: 1 };
+        for card in cards {
+            deck.push_back(*card);
         }
+        deck
     }
-}
 
-fn determine_winner(player1: &VecDeque<u64>, player1_card: &u64, player2: &VecDeque<u64>, player2_card: &u64, recursive: bool) -> Winner {
-    if !recursive || player1.len() < *player1_card as usize || player2.len() < *player2_card as usize {
-        // Determined by the cards.
-        if player1_card > player2_card { Winner::Player1 } else { Winner::Player2 }
-    } else {
-        // Determined by sub-game.
-        let mut player1_clone = player1.clone();
-        let mut player2_clone = player2.clone();
-        player1_clone.truncate(*player1_card as usize);
-        player2_clone.truncate(*player2_card as usize);
-        play_game(&mut player1_clone, &mut player2_clone, recursive)
+    fn cards(&self) -> &[u64] {
+        &self.history[self.top..]
+    }
+
+    fn span(&self) -> Span {
+        (self.top as u32, self.history.len() as u32)
+    }
+
+    fn was(&self, span: Span) -> bool {
+        self.history[span.0 as usize..span.1 as usize] == *self.cards()
+    }
+
+    fn push_back(&mut self, card: u64) {
+        if !self.is_empty() {
+            self.top_place_value = self.top_place_value.wrapping_mul(HASH_BASE);
+        }
+        self.hash = self.hash.wrapping_mul(HASH_BASE).wrapping_add(card);
+        self.history.push(card);
     }
-}
 
-fn winning_score(player1: &VecDeque<u64>, player2: &VecDeque<u64>) -> u64 {
-    if player2.is_empty() {
-        score(player1)
-    } else {
-        score(player2)
+    fn pop_front(&mut self) -> u64 {
+        let card = self.history[self.top];
+        self.top += 1;
+        self.hash = self.hash.wrapping_sub(card.wrapping_mul(self.top_place_value));
+        self.top_place_value = if self.is_empty() { 1 } else { self.top_place_value.wrapping_mul(*HASH_BASE_INVERSE) };
+        card
+    }
+
+    fn len(&self) -> usize {
+        self.cards().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cards().is_empty()
+    }
+
+    fn highest_card(&self) -> u64 {
+        self.cards().iter().copied().max().unwrap_or(0)
+    }
+
+    fn score(&self) -> u64 {
+        self.cards().iter().rev().enumerate().map(|(index, card)| (index + 1) as u64 * *card).sum()
     }
 }
 
-fn score(deck: &VecDeque<u64>) -> u64 {
-    deck.iter().rev().enumerate().map(|(index, card)| (index + 1) as u64 * *card).sum()
-}
\ No newline at end of file
+// Both players' starting decks, and who won.
+type SubGameResult = (Vec<u64>, Vec<u64>, Winner);
+
+struct Combat {
+    recursive: bool,
+    // By the hashes of the starting decks, along with the decks themselves to
+    // check against in case of a collision.
+    sub_game_results: HashMap<(u64, u64), Vec<SubGameResult>>,
+    replay: Option<Replay>,
+}
+
+impl Combat {
+    fn new(recursive: bool) -> Self {
+        Self { recursive, sub_game_results: HashMap::new(), replay: None }
+    }
+
+    // Play a whole game, and return the winner's score.
+    fn play(&mut self, player1: &[u64], player2: &[u64]) -> u64 {
+        let mut player1 = Deck::new(player1);
+        let mut player2 = Deck::new(player2);
+        match self.play_game(&mut player1, &mut player2, 0) {
+            Winner::Player1 => player1.score(),
+            Winner::Player2 => player2.score(),
+        }
+    }
+
+    fn play_game(&mut self, player1: &mut Deck, player2: &mut Deck, depth: usize) -> Winner {
+        if depth > 0 && self.replay.is_none() {
+            let highest_card = player1.highest_card();
+            if highest_card > player2.highest_card() && highest_card as usize > player1.len() + player2.len() - 2 {
+                return Winner::Player1;
+            }
+        }
+
+        let game = self.replay.as_mut().map_or(0, |replay| replay.start_game(depth));
+        // The first state seen with each pair of hashes, and any later ones that
+        // turned out to be different states with the same hashes.
+        let mut seen_states: HashMap<(u64, u64), (Span, Span)> = HashMap::new();
+        let mut collisions: Vec<((u64, u64), (Span, Span))> = Vec::new();
+        let mut rounds = 0;
+        let winner = loop {
+            if player1.is_empty() || player2.is_empty() {
+                break if player2.is_empty() { Winner::Player1 } else { Winner::Player2 };
+            }
+            rounds += 1;
+            let hashes = (player1.hash, player2.hash);
+            let spans = (player1.span(), player2.span());
+            let is_state = |&(span1, span2): &(Span, Span)| player1.was(span1) && player2.was(span2);
+            let repeated = match seen_states.entry(hashes) {
+                Entry::Vacant(entry) => { entry.insert(spans); false },
+                Entry::Occupied(entry) if is_state(entry.get()) => true,
+                Entry::Occupied(_) if collisions.iter().any(|(other, spans)| *other == hashes && is_state(spans)) => true,
+                Entry::Occupied(_) => { collisions.push((hashes, spans)); false },
+            };
+            if repeated {
+                if let Some(replay) = self.replay.as_mut() {
+                    replay.round(game, depth, rounds, None, Winner::Player1, Reason::Repeat);
+                }
+                break Winner::Player1;
+            }
+            self.play_round(player1, player2, game, depth, rounds);
+        };
+
+        if let Some(replay) = self.replay.as_mut() {
+            replay.end_game(game, depth, rounds);
+        }
+        winner
+    }
+
+    fn play_round(&mut self, player1: &mut Deck, player2: &mut Deck, game: u64, depth: usize, round: u64) {
+        let player1_card = player1.pop_front();
+        let player2_card = player2.pop_front();
+        let (winner, reason) = self.determine_winner(player1, player1_card, player2, player2_card, depth);
+        if let Some(replay) = self.replay.as_mut() {
+            replay.round(game, depth, round, Some((player1_card, player2_card)), winner, reason);
+        }
+        match winner {
+            Winner::Player1 => {
+                player1.push_back(player1_card);
+                player1.push_back(player2_card);
+            },
+            Winner::Player2 => {
+                player2.push_back(player2_card);
+                player2.push_back(player1_card);
+            }
+        }
+    }
+
+    fn determine_winner(&mut self, player1: &Deck, player1_card: u64, player2: &Deck, player2_card: u64, depth: usize) -> (Winner, Reason) {
+        if !self.recursive || player1.len() < player1_card as usize || player2.len() < player2_card as usize {
+            // Determined by the cards.
+            (if player1_card > player2_card { Winner::Player1 } else { Winner::Player2 }, Reason::HigherCard)
+        } else {
+            // Determined by sub-game.
+            let mut sub_player1 = Deck::new(&player1.cards()[..player1_card as usize]);
+            let mut sub_player2 = Deck::new(&player2.cards()[..player2_card as usize]);
+            if self.replay.is_some() {
+                return (self.play_game(&mut sub_player1, &mut sub_player2, depth + 1), Reason::SubGame);
+            }
+            let key = (sub_player1.hash, sub_player2.hash);
+            let cached = self.sub_game_results.get(&key).into_iter().flatten()
+                .find(|(cards1, cards2, _)| *cards1 == sub_player1.cards() && *cards2 == sub_player2.cards());
+            if let Some((_, _, winner)) = cached {
+                return (*winner, Reason::SubGame);
+            }
+            let (cards1, cards2) = (sub_player1.cards().to_vec(), sub_player2.cards().to_vec());
+            let winner = self.play_game(&mut sub_player1, &mut sub_player2, depth + 1);
+            self.sub_game_results.entry(key).or_default().push((cards1, cards2, winner));
+            (winner, Reason::SubGame)
+        }
+    }
+}
@@ -1,137 +1,111 @@
-use std::rc::Rc;
-use std::cell::RefCell;
-use std::collections::HashMap;
-
-pub fn day23(_input_lines: &[String]) -> (u64, u64) {
-    let input: [usize;9] = [1,8,6,5,2,4,9,7,3];
-    let part_1_cups = build_cups_array(&input, 9);
-    play_game(&part_1_cups, input[0], 100);
-    let part1 = get_cup_labels_after_1(&part_1_cups);
-    let part_2_cups = build_cups_array(&input, 1000000);
-    play_game(&part_2_cups, input[0], 10000000);
-    let first_cup_after_1 = part_2_cups[1].borrow().next.as_ref().unwrap().clone();
-    let second_cup_after_1 = first_cup_after_1.borrow().next.as_ref().unwrap().clone();
-    let part2 = (first_cup_after_1.borrow().number * second_cup_after_1.borrow().number) as u64;
+use crate::utils;
+
+// The cups live in a ring, stored as a single array where next[label] is the
+// label of the cup clockwise of it - so moving a run of cups is just a case of
+// updating three entries.  Entry 0 is unused, to save adjusting every label.
+// The array is padded out to a power of two long, so masking each label proves
+// to the compiler that it's in range, and the main loop has no bounds checks.
+//
+// The input is the starting labels on the first line, then optionally the number
+// of moves for part 1 and for part 2 on the next two lines.  --moves=<n>,
+// --part2-moves=<n> and --cups=<n> override those and the number of cups in
+// part 2.
+
+const PART1_MOVES: usize = 100;
+const PART2_MOVES: usize = 10_000_000;
+const PART2_CUPS: usize = 1_000_000;
+
+pub fn day23(input_lines: &[String]) -> (u64, u64) {
+    let labels = parse_labels(input_lines.first().map(|line| line.trim()).filter(|line| !line.is_empty()).expect("Invalid input: no cup labels"));
+    let part1_moves = move_count("moves", input_lines.get(1), PART1_MOVES);
+    let part2_moves = move_count("part2-moves", input_lines.get(2), PART2_MOVES);
+    let part2_cups = utils::option_value("cups").map_or(PART2_CUPS, |cups| cups.parse().expect("Invalid --cups"));
+
+    let mut ring = CupRing::new(&labels, labels.len());
+    ring.play(part1_moves);
+    let part1 = ring.labels_after(1, labels.len() - 1).iter().fold(0u64, |total, &label| total * 10 + label as u64);
+
+    let mut ring = CupRing::new(&labels, part2_cups);
+    ring.play(part2_moves);
+    let part2 = ring.labels_after(1, 2).iter().map(|&label| label as u64).product();
     (part1,part2)
 }
 
-fn play_game(cups: &[Rc<RefCell<Cup>>], first_cup: usize, rounds: usize) {
-    let mut current_cup = cups[first_cup].clone();
-    for _ in 0..rounds {
-        let detached_cups = current_cup.borrow_mut().detach_after(3);
-        let destination_cup = &cups[find_valid_destination(current_cup.borrow().number, &detached_cups, cups.len() - 1)];
-        destination_cup.borrow_mut().insert_after(detached_cups);
-        let next_cup = current_cup.borrow().next.as_ref().unwrap().clone();
-        current_cup = next_cup;
-    }
-}
-
-fn get_cup_labels_after_1(cups: &[Rc<RefCell<Cup>>]) -> u64 {
-    let mut current_cup = cups[1].clone();
-    let mut labels = 0u64;
-    for _ in 0..cups.len()-1 {
-        let next_cup = current_cup.borrow().next.as_ref().unwrap().clone();
-        current_cup = next_cup;
-        labels *= 10;
-        labels += current_cup.borrow().number as u64;
+fn parse_labels(line: &str) -> Vec<u32> {
+    let labels: Vec<u32> = line.chars().map(|c| c.to_digit(10).expect("Invalid input: cup labels must be digits")).collect();
+    let mut sorted = labels.clone();
+    sorted.sort_unstable();
+    if !sorted.iter().enumerate().all(|(index, &label)| label as usize == index + 1) {
+        panic!("Invalid input: cups must be labelled 1 to {} with no gaps or repeats", labels.len());
     }
     labels
 }
 
-fn find_valid_destination(current_cup_num: usize, detached_cups: &Rc<RefCell<Cup>>, num_cups: usize) -> usize {
-    let mut destination_cup_num = current_cup_num - 1;
-    if destination_cup_num == 0 { destination_cup_num = num_cups; }
-    while detached_cups.borrow().contains_number(destination_cup_num) {
-        destination_cup_num -= 1;
-        if destination_cup_num == 0 { destination_cup_num = num_cups; }
+// The command line beats the input, which beats the default.
+fn move_count(option: &str, input_line: Option<&String>, default: usize) -> usize {
+    match utils::option_value(option) {
+        Some(moves) => moves.parse().unwrap_or_else(|_| panic!("Invalid --{}", option)),
+        None => input_line.filter(|line| !line.trim().is_empty()).map_or(default, |line| line.trim().parse().expect("Invalid input: move count")),
     }
-    destination_cup_num
 }
 
-fn build_cups_array(initial_input: &[usize], total_cups: usize) -> Vec<Rc<RefCell<Cup>>> {
-    let mut cups: HashMap<usize, Rc<RefCell<Cup>>> = HashMap::with_capacity(total_cups+1);
-
-    // Build the first cup.
-    let first_cup = Rc::new(RefCell::new(Cup::new(initial_input[0])));
-    cups.insert(initial_input[0], first_cup.clone());
-    let mut previous_cup = first_cup.clone();
-
-    // Build the remaining cups in the provided input.
-    for &num in initial_input.iter().skip(1) {
-        let cup = Rc::new(RefCell::new(Cup::new(num)));
-        previous_cup.borrow_mut().next = Some(cup.clone());
-        cups.insert(num, cup.clone());
-        previous_cup = cup;
-    }
-
-    // Build the additional cups beyond the provided input.
-    for num in initial_input.len()+1..=total_cups {
-        let cup = Rc::new(RefCell::new(Cup::new(num)));
-        previous_cup.borrow_mut().next = Some(cup.clone());
-        cups.insert(num, cup.clone());
-        previous_cup = cup;
-    }
-
-    // Link the last cup back to the first.
-    previous_cup.borrow_mut().next = Some(first_cup);
-
-    // Stick in a dummy cup 0.
-    cups.insert(0, Rc::new(RefCell::new(Cup::new(0))));
-
-    (0..=total_cups).into_iter().map(|num| cups.get(&num).unwrap().clone()).collect()
+struct CupRing {
+    next: Vec<u32>,
+    highest_label: u32,
+    current: u32,
 }
 
-#[derive(Debug)]
-struct Cup {
-    number: usize,
-    next: Option<Rc<RefCell<Cup>>>,
-}
-
-impl Cup {
-    fn new(number: usize) -> Self {
-        Self {
-            number,
-            next: None,
+impl CupRing {
+    // Lay out the given labels, followed by cups labelled from one more than the
+    // highest given label up to total_cups.
+    fn new(labels: &[u32], total_cups: usize) -> Self {
+        let total_cups = total_cups.max(labels.len());
+        // With fewer than five, there might be nowhere to put the cups we pick up.
+        assert!(total_cups >= 5, "Need at least 5 cups");
+        assert!(total_cups < u32::MAX as usize, "Too many cups");
+        let mut next = vec![0u32; (total_cups + 1).next_power_of_two()];
+        let order = labels.iter().copied().chain(labels.len() as u32 + 1..=total_cups as u32);
+        let mut previous = labels[0];
+        for label in order.skip(1) {
+            next[previous as usize] = label;
+            previous = label;
         }
+        next[previous as usize] = labels[0];
+        Self { next, highest_label: total_cups as u32, current: labels[0] }
     }
 
-    fn detach_after(&mut self, how_many: usize) -> Rc<RefCell<Cup>> {
-        let first_detached_cup = self.next.take().unwrap();
-        let mut next_cup = first_detached_cup.borrow().next.as_ref().unwrap().clone();
-        for _ in 0..how_many-2 {
-            let next_next_cup = next_cup.borrow().next.as_ref().unwrap().clone();
-            next_cup = next_next_cup;
+    fn play(&mut self, moves: usize) {
+        let mask = self.next.len() - 1;
+        let next = &mut self.next[..=mask];
+        let at = |label: u32| label as usize & mask;
+        let mut current = self.current;
+        for _ in 0..moves {
+            let first = next[at(current)];
+            let second = next[at(first)];
+            let third = next[at(second)];
+
+            let mut destination = current;
+            loop {
+                destination = if destination == 1 { self.highest_label } else { destination - 1 };
+                if destination != first && destination != second && destination != third { break; }
+            }
+
+            // Close the gap, then splice the picked-up cups in after the destination.
+            next[at(current)] = next[at(third)];
+            next[at(third)] = next[at(destination)];
+            next[at(destination)] = first;
+            current = next[at(current)];
         }
-        let cup_after_detached_section = next_cup.borrow_mut().next.take().unwrap();
-        self.next = Some(cup_after_detached_section);
-        first_detached_cup
+        self.current = current;
     }
 
-    fn insert_after(&mut self, first_cup_to_insert: Rc<RefCell<Cup>>) {
-        let cup_after_insertion = self.next.take().unwrap();
-        let mut next_cup = first_cup_to_insert.clone();
-        loop {
-            if next_cup.borrow().next.is_none() { break; }
-            let next_next_cup = next_cup.borrow().next.as_ref().unwrap().clone();
-            next_cup = next_next_cup;
-        }
-        next_cup.borrow_mut().next = Some(cup_after_insertion);
-        self.next = Some(first_cup_to_insert);
-    }
-
-    fn contains_number(&self, number: usize) -> bool {
-        if self.number == number {
-            true
-        } else if let Some(next_cup) = self.next.as_ref() {
-            next_cup.borrow().contains_number(number)
-        } else {
-            false
+    fn labels_after(&self, cup: u32, count: usize) -> Vec<u32> {
+        let mut labels = Vec::with_capacity(count);
+        let mut label = cup;
+        for _ in 0..count {
+            label = self.next[label as usize];
+            labels.push(label);
         }
+        labels
     }
 }
-
-impl std::hash::Hash for Cup {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.number.hash(state);
-    }
-}
\ No newline at end of file
@@ -1,37 +1,35 @@
+use crate::modular;
+use crate::utils;
+
+// The input is the card's public key then the door's, one per line.  The loop
+// size is a discrete logarithm, which we find directly rather than counting up
+// to it.  --modulus=<n> and --subject=<n> change the handshake's parameters.
+
 const CARD_PUBLIC_KEY: u64 = 6930903;
 const DOOR_PUBLIC_KEY: u64 = 19716708;
+const MODULUS: u64 = 20201227;
+const SUBJECT_NUMBER: u64 = 7;
 
-pub fn day25(_input_lines: &[String]) -> (u64, u64) {
-    let mut candidate_loop_size = 0u64;
-    let mut value = 1u64;
-    let mut card_loop_size: Option<u64> = None;
-    let mut door_loop_size: Option<u64> = None;
-    
-    while card_loop_size.is_none() || door_loop_size.is_none() {
-        candidate_loop_size += 1;
-        value = transform_once(value, 7);
-
-        if card_loop_size.is_none() && value == CARD_PUBLIC_KEY {
-            card_loop_size = Some(candidate_loop_size);
-        }
-        if door_loop_size.is_none() && value == DOOR_PUBLIC_KEY {
-            door_loop_size = Some(candidate_loop_size);
-        }
+pub fn day25(input_lines: &[String]) -> (u64, u64) {
+    let keys: Vec<u64> = input_lines.iter().filter(|line| !line.trim().is_empty()).map(|line| line.trim().parse().expect("Invalid input")).collect();
+    let (card_public_key, door_public_key) = match keys[..] {
+        [] => (CARD_PUBLIC_KEY, DOOR_PUBLIC_KEY),
+        [card, door] => (card, door),
+        _ => panic!("Invalid input: expected the card's and the door's public keys"),
+    };
+    let modulus = numeric_option("modulus", MODULUS);
+    if modulus < 2 {
+        panic!("Invalid --modulus: it has to be at least 2");
     }
+    let subject_number = numeric_option("subject", SUBJECT_NUMBER);
+
+    let door_loop_size = modular::discrete_log(subject_number, door_public_key, modulus)
+        .unwrap_or_else(|| panic!("Invalid input: no loop size turns {} into {} (mod {})", subject_number, door_public_key, modulus));
+    let part1 = modular::pow_mod(card_public_key, door_loop_size, modulus);
 
-    let part1 = transform(CARD_PUBLIC_KEY, door_loop_size.unwrap());
-    
     (part1,49)
 }
 
-fn transform(subject: u64, loop_size: u64) -> u64 {
-    let mut value = 1u64;
-    for _ in 0..loop_size {
-        value = transform_once(value, subject)
-    }
-    value
+fn numeric_option(name: &str, default: u64) -> u64 {
+    utils::option_value(name).map_or(default, |value| value.parse().unwrap_or_else(|_| panic!("Invalid --{}", name)))
 }
-
-fn transform_once(value: u64, subject: u64) -> u64 {
-    (value * subject) % 20201227
-}
\ No newline at end of file
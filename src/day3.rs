@@ -1,10 +1,89 @@
+use std::fmt::{self, Display};
+use crate::utils;
+
+// Run with --explore=<right>,<down> to try every slope in a range and rank them
+// by how many trees they hit, where each of right and down is a number or a
+// range like 1-7.  --slopes=<file> ranks the slopes listed in a file instead
+// (one "<right>,<down>" per line), or as well.
+//
+// Run with --draw to draw part 1's path over the forest, with the trees it hit
+// marked X and the open squares it crossed marked O, or --draw=<right>,<down>
+// to draw a different slope's.  The forest repeats to the right, so the path
+// wraps around within the one map.
+
 pub fn day3(input_lines: &[String]) -> (u64, u64) {
     let forest = Forest::new(input_lines);
-    let part1 = forest.traverse((3, 1));
-    let part2: u64 = vec![(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)].iter().map(|delta| forest.traverse(*delta)).product();
+    let part1 = forest.traverse(Slope { right: 3, down: 1 });
+    let part2: u64 = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)].iter().map(|&(right, down)| forest.traverse(Slope { right, down })).product();
+
+    let mut slopes = Vec::new();
+    if let Some(ranges) = utils::option_value("explore") {
+        slopes.extend(Slope::all_in_ranges(&ranges));
+    }
+    if let Some(file) = utils::option_value("slopes") {
+        let contents = std::fs::read_to_string(&file).expect("Can't read slopes file");
+        slopes.extend(contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(Slope::parse));
+    }
+    if !slopes.is_empty() {
+        let mut ranked: Vec<(Slope, u64)> = slopes.iter().map(|&slope| (slope, forest.traverse(slope))).collect();
+        ranked.sort_by_key(|&(_, trees)| trees);
+        println!("Rank  Slope    Trees");
+        for (rank, (slope, trees)) in ranked.iter().enumerate() {
+            println!("{:>4}  {:<7}  {}", rank + 1, slope.to_string(), trees);
+        }
+    }
+
+    if utils::option_set("draw") {
+        let slope = utils::option_value("draw").map_or(Slope { right: 3, down: 1 }, |slope| Slope::parse(&slope));
+        println!("Slope {}:", slope);
+        print!("{}", forest.draw(slope));
+    }
+
     (part1, part2)
 }
 
+#[derive(Clone, Copy)]
+struct Slope {
+    right: usize,
+    down: usize,
+}
+
+impl Slope {
+    fn parse(input: &str) -> Self {
+        let (right, down) = input.split_once(',').expect("Invalid slope: expected <right>,<down>");
+        let slope = Slope {
+            right: right.trim().parse().expect("Invalid slope: right isn't a number"),
+            down: down.trim().parse().expect("Invalid slope: down isn't a number"),
+        };
+        if slope.down == 0 {
+            panic!("Invalid slope {}: it has to go down, or it'll never reach the bottom", slope);
+        }
+        slope
+    }
+
+    // Every slope in "<right>,<down>", where each is a number or a range like 1-7.
+    fn all_in_ranges(input: &str) -> Vec<Self> {
+        let parse_range = |range: &str| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let start: usize = start.trim().parse().expect("Invalid slope range");
+            let end: usize = end.trim().parse().expect("Invalid slope range");
+            start..=end
+        };
+        let (rights, downs) = input.split_once(',').expect("Invalid slope range: expected <right>,<down>");
+        let downs = parse_range(downs);
+        if *downs.start() == 0 {
+            panic!("Invalid slope range: slopes have to go down, or they'll never reach the bottom");
+        }
+        parse_range(rights).flat_map(|right| downs.clone().map(move |down| Slope { right, down })).collect()
+    }
+}
+
+impl Display for Slope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.right, self.down)
+    }
+}
+
 struct Forest {
     tree_positions: Vec<u64>,
     pattern_width: usize,
@@ -27,16 +106,22 @@ impl Forest {
         Forest { tree_positions, pattern_width, pattern_height }
     }
 
-    fn traverse(&self, delta: (usize, usize)) -> u64 {
-        let (delta_x, delta_y) = delta;
-        let mut tree_count: u64 = 0;
-        let mut position_x: usize = 0;
-        let mut position_y: usize = 0;
-        while position_y < self.pattern_height {
-            tree_count += self.tree_positions[(position_y * self.pattern_width) + (position_x % self.pattern_width)];
-            position_x += delta_x;
-            position_y += delta_y;
+    // Where the slope visits in the map, as indices into tree_positions.
+    fn path(&self, slope: Slope) -> impl Iterator<Item = usize> + '_ {
+        (0..self.pattern_height).step_by(slope.down).enumerate().map(move |(step, position_y)| {
+            (position_y * self.pattern_width) + ((step * slope.right) % self.pattern_width)
+        })
+    }
+
+    fn traverse(&self, slope: Slope) -> u64 {
+        self.path(slope).map(|position| self.tree_positions[position]).sum()
+    }
+
+    fn draw(&self, slope: Slope) -> String {
+        let mut map: Vec<char> = self.tree_positions.iter().map(|&tree| if tree == 1 { '#' } else { '.' }).collect();
+        for position in self.path(slope) {
+            map[position] = if self.tree_positions[position] == 1 { 'X' } else { 'O' };
         }
-        tree_count
+        map.chunks(self.pattern_width).map(|row| row.iter().collect::<String>() + "\n").collect()
     }
-}
\ No newline at end of file
+}
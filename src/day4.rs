@@ -1,22 +1,227 @@
 use regex::Regex;
-use std::str::FromStr;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
 use crate::utils;
 
+// Which fields a passport needs, and what makes each of them valid, comes from a
+// schema - by default day4_rules.toml, or run with --rules=<file> to use a
+// different TOML or JSON one.
+//
+// Run with --report to see every field of every passport and what was wrong
+// with it, followed by how often each rule was broken, or --report=json to get
+// the same thing as JSON.
+
+static DEFAULT_RULES: &str = include_str!("day4_rules.toml");
+
 pub fn day4(input_lines: &[String]) -> (u64, u64) {
-    let passports: Vec<Passport> = utils::group_lines_split_by_empty_line(input_lines).iter().map(|group| Passport::decode(group)).collect();
+    let schema = match utils::option_value("rules") {
+        Some(file) => Schema::load(&file),
+        None => Schema::parse(DEFAULT_RULES, false).expect("Invalid default rules"),
+    };
+    let passports: Vec<Passport> = utils::group_lines_split_by_empty_line(input_lines).iter().map(|group| Passport::decode(group, &schema)).collect();
     let part1 = passports.iter().filter(|passport| passport.all_fields_present()).count() as u64;
     let part2 = passports.iter().filter(|passport| passport.all_fields_valid()).count() as u64;
+
+    if utils::option_set("report") {
+        if utils::option_value("report").as_deref() == Some("json") {
+            println!("{}", report_json(&passports));
+        } else {
+            for (index, passport) in passports.iter().enumerate() {
+                println!("Passport {}: {}", index + 1, if passport.all_fields_valid() { "valid" } else { "invalid" });
+                println!("{}", passport);
+            }
+            println!("Failures:");
+            for (failure, count) in failure_counts(&passports) {
+                println!("  {}: {}", failure, count);
+            }
+        }
+    }
+
     (part1,part2)
 }
 
-enum FieldState<T> {
-    Valid(T),
-    Invalid,
+// How many times each rule was broken, across all the passports.
+fn failure_counts(passports: &[Passport]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for passport in passports {
+        for (rule, field) in passport.schema.fields.iter().zip(&passport.fields) {
+            if let Some(failure) = field.state.failure(rule) {
+                *counts.entry(format!("{} {}", rule.name, failure)).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+fn report_json(passports: &[Passport]) -> String {
+    let passports_json: Vec<serde_json::Value> = passports.iter().enumerate().map(|(index, passport)| {
+        let fields: Vec<serde_json::Value> = passport.schema.fields.iter().zip(&passport.fields).map(|(rule, field)| {
+            let (status, parsed) = match &field.state {
+                FieldState::Valid(value) => ("valid", Some(value.to_string())),
+                FieldState::Invalid(_) => ("invalid", None),
+                FieldState::Missing => ("missing", None),
+            };
+            serde_json::json!({
+                "name": rule.name,
+                "description": rule.description,
+                "raw": field.raw,
+                "parsed": parsed,
+                "status": status,
+                "failed_rule": field.state.failure(rule),
+            })
+        }).collect();
+        serde_json::json!({ "passport": index + 1, "valid": passport.all_fields_valid(), "fields": fields })
+    }).collect();
+    serde_json::to_string_pretty(&serde_json::json!({ "passports": passports_json, "failures": failure_counts(passports) })).unwrap()
+}
+
+#[derive(Deserialize)]
+struct Schema {
+    #[serde(rename = "field")]
+    fields: Vec<FieldRule>,
+}
+
+#[derive(Deserialize)]
+struct FieldRule {
+    name: String,
+    description: String,
+    #[serde(default = "required_by_default")]
+    required: bool,
+    #[serde(default, with = "pattern")]
+    pattern: Option<Pattern>,
+    #[serde(flatten)]
+    kind: FieldKind,
+}
+
+fn required_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum FieldKind {
+    Integer {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Measurement {
+        units: BTreeMap<String, Limits>,
+    },
+    Colour,
+    Enum {
+        values: BTreeMap<String, String>,
+    },
+    String,
+}
+
+#[derive(Deserialize)]
+struct Limits {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl Limits {
+    // Why the value's out of range, if it is.
+    fn check(&self, value: i64) -> Result<(), String> {
+        match (self.min, self.max) {
+            (Some(min), _) if value < min => Err(format!("is below {}", min)),
+            (_, Some(max)) if value > max => Err(format!("is above {}", max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+// Patterns have to match the whole value, so anchor them.
+mod pattern {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer};
+    use super::Pattern;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Pattern>, D::Error> {
+        let source: Option<String> = Option::deserialize(deserializer)?;
+        source.map(|source| {
+            let regex = Regex::new(&format!("^(?:{})$", source)).map_err(serde::de::Error::custom)?;
+            Ok(Pattern { source, regex })
+        }).transpose()
+    }
+}
+
+impl Schema {
+    fn load(file: &str) -> Self {
+        let contents = std::fs::read_to_string(file).expect("Can't read rules file");
+        Self::parse(&contents, file.ends_with(".json")).unwrap_or_else(|error| panic!("Invalid rules in {}: {}", file, error))
+    }
+
+    fn parse(contents: &str, json: bool) -> Result<Self, String> {
+        if json {
+            serde_json::from_str(contents).map_err(|error| error.to_string())
+        } else {
+            toml::from_str(contents).map_err(|error| error.to_string())
+        }
+    }
+}
+
+impl FieldRule {
+    fn check(&self, value: &str) -> FieldState {
+        match self.parse(value) {
+            Ok(value) => FieldState::Valid(value),
+            Err(failure) => FieldState::Invalid(failure),
+        }
+    }
+
+    // The failures are worded to follow the field's name, and don't mention the
+    // value itself, so that the same failure always reads the same way.
+    fn parse(&self, value: &str) -> Result<Value, String> {
+        if let Some(pattern) = &self.pattern {
+            if !pattern.regex.is_match(value) {
+                return Err(format!("doesn't match {}", pattern.source));
+            }
+        }
+        match &self.kind {
+            FieldKind::Integer { min, max } => {
+                let number = value.parse::<i64>().map_err(|_| "isn't a whole number".to_string())?;
+                Limits { min: *min, max: *max }.check(number)?;
+                Ok(Value::Integer(number))
+            },
+            FieldKind::Measurement { units } => {
+                let unit_start = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+                let (number, unit) = value.split_at(unit_start);
+                let number = number.parse::<i64>().map_err(|_| "doesn't start with a whole number".to_string())?;
+                let known_units = || units.keys().cloned().collect::<Vec<String>>().join(", ");
+                let limits = units.get(unit).ok_or_else(|| format!("isn't in any of {}", known_units()))?;
+                limits.check(number).map_err(|failure| format!("{} {}", failure, unit))?;
+                Ok(Value::Measurement(number, unit.to_string()))
+            },
+            FieldKind::Colour => {
+                lazy_static! {
+                    static ref RE: Regex = Regex::new(r"^#([0-9a-f]{6})$").unwrap();
+                }
+                let caps = RE.captures(value).ok_or_else(|| "isn't # and six lowercase hex digits".to_string())?;
+                let bytes = hex::decode(caps.get(1).unwrap().as_str()).unwrap(); // regex already validated as hex string
+                Ok(Value::Colour(Colour { r: bytes[0], g: bytes[1], b: bytes[2] }))
+            },
+            FieldKind::Enum { values } => match values.get(value) {
+                Some(name) => Ok(Value::Enum(name.clone())),
+                None => Err(format!("isn't one of {}", values.keys().cloned().collect::<Vec<String>>().join(", "))),
+            },
+            FieldKind::String => Ok(Value::String(value.to_string())),
+        }
+    }
+}
+
+enum FieldState {
+    Valid(Value),
+    Invalid(String),
     Missing,
 }
 
-impl<T> FieldState<T> {
+impl FieldState {
     fn is_present(&self) -> bool {
         !matches!(self, Self::Missing)
     }
@@ -24,28 +229,48 @@ impl<T> FieldState<T> {
     fn is_valid(&self) -> bool {
         matches!(self, Self::Valid(_))
     }
+
+    // What's wrong with the field, if anything.
+    fn failure(&self, rule: &FieldRule) -> Option<String> {
+        match self {
+            Self::Valid(_) => None,
+            Self::Invalid(failure) => Some(failure.clone()),
+            Self::Missing => if rule.required { Some("is missing".to_string()) } else { None },
+        }
+    }
 }
 
-impl<T: Display> Display for FieldState<T> {
+impl Display for FieldState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Valid(field) => write!(f, "{}", field),
-            Self::Invalid => write!(f, "**INVALID**"),
+            Self::Invalid(failure) => write!(f, "**INVALID** ({})", failure),
             Self::Missing => write!(f, "**MISSING**"),
         }
     }
 }
 
-enum Height {
-    Centimetres(u32),
-    Inches(u32),
+struct Field {
+    raw: Option<String>,
+    state: FieldState,
 }
 
-impl Display for Height {
+enum Value {
+    Integer(i64),
+    Measurement(i64, String),
+    Colour(Colour),
+    Enum(String),
+    String(String),
+}
+
+impl Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Centimetres(cm) => write!(f, "{}cm", cm),
-            Self::Inches(inches) => write!(f, "{}in", inches),
+            Self::Integer(number) => write!(f, "{}", number),
+            Self::Measurement(number, unit) => write!(f, "{}{}", number, unit),
+            Self::Colour(colour) => write!(f, "{}", colour),
+            Self::Enum(name) => write!(f, "{}", name),
+            Self::String(string) => write!(f, "{}", string),
         }
     }
 }
@@ -62,58 +287,33 @@ impl Display for Colour {
     }
 }
 
-#[derive(EnumString,Display)]
-enum EyeColour {
-    #[strum(serialize = "amb")]
-    Amber,
-
-    #[strum(serialize = "blu")]
-    Blue,
-    
-    #[strum(serialize = "brn")]
-    Brown,
-
-    #[strum(serialize = "gry")]
-    Grey,
-
-    #[strum(serialize = "grn")]
-    Green,
-
-    #[strum(serialize = "hzl")]
-    Hazel,
-
-    #[strum(serialize = "oth")]
-    Other,
-}
-
-struct Passport {
+struct Passport<'a> {
     raw: String,
-    byr: FieldState<u32>,
-    iyr: FieldState<u32>,
-    eyr: FieldState<u32>,
-    hgt: FieldState<Height>,
-    hcl: FieldState<Colour>,
-    ecl: FieldState<EyeColour>,
-    pid: FieldState<String>,
+    schema: &'a Schema,
+    // One for each field in the schema, in the same order.
+    fields: Vec<Field>,
 }
 
-impl Display for Passport {
+impl Display for Passport<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}Birth year: {}\nIssue year: {}\nExpiration year: {}\nHeight: {}\nHair colour: {}\nEye colour: {}\nPassport ID: {}\n---------------", self.raw, self.byr, self.iyr, self.eyr, self.hgt, self.hcl, self.ecl, self.pid)
+        write!(f, "{}", self.raw)?;
+        for (rule, field) in self.schema.fields.iter().zip(&self.fields) {
+            match (&field.raw, &field.state) {
+                (None, _) if !rule.required => writeln!(f, "{}: not given", rule.description)?,
+                (None, state) => writeln!(f, "{}: {}", rule.description, state)?,
+                (Some(raw), state) => writeln!(f, "{}: {} -> {}", rule.description, raw, state)?,
+            }
+        }
+        write!(f, "---------------")
     }
 }
 
-impl Passport {
-    fn decode(input_lines: &[String]) -> Self {
+impl<'a> Passport<'a> {
+    fn decode(input_lines: &[String], schema: &'a Schema) -> Self {
         let mut passport = Self {
             raw: "".to_string(),
-            byr: FieldState::Missing,
-            iyr: FieldState::Missing,
-            eyr: FieldState::Missing,
-            hgt: FieldState::Missing,
-            hcl: FieldState::Missing,
-            ecl: FieldState::Missing,
-            pid: FieldState::Missing,
+            schema,
+            fields: schema.fields.iter().map(|_| Field { raw: None, state: FieldState::Missing }).collect(),
         };
 
         for line in input_lines {
@@ -130,115 +330,21 @@ impl Passport {
             static ref RE: Regex = Regex::new(r"([a-z]{3}):([^ ]+)").unwrap();
         }
         for field in RE.captures_iter(input) {
-            match &field[1] {
-                "byr" => self.set_byr(&field[2]),
-                "iyr" => self.set_iyr(&field[2]),
-                "eyr" => self.set_eyr(&field[2]),
-                "hgt" => self.set_hgt(&field[2]),
-                "hcl" => self.set_hcl(&field[2]),
-                "ecl" => self.set_ecl(&field[2]),
-                "pid" => self.set_pid(&field[2]),
-                _ => (),
-            }            
-        }
-    }
-
-    fn set_byr(&mut self, val: &str) {
-        self.byr = FieldState::Invalid;
-        let num = val.parse::<u32>();
-        if let Ok(byr) = num {
-            if byr >= 1920 && byr <= 2002 {
-                self.byr = FieldState::Valid(byr);
+            // Fields that aren't in the schema are ignored.
+            if let Some(index) = self.schema.fields.iter().position(|rule| rule.name == field[1]) {
+                self.fields[index] = Field { raw: Some(field[2].to_string()), state: self.schema.fields[index].check(&field[2]) };
             }
         }
     }
 
-    fn set_iyr(&mut self, val: &str) {
-        self.iyr = FieldState::Invalid;
-        let num = val.parse::<u32>();
-        if let Ok(iyr) = num {
-            if iyr >= 2010 && iyr <= 2020 {
-                self.iyr = FieldState::Valid(iyr)
-            }
-        }
-    }
-
-    fn set_eyr(&mut self, val: &str) {
-        self.eyr = FieldState::Invalid;
-        let num = val.parse::<u32>();
-        if let Ok(eyr) = num {
-            if eyr >= 2020 && eyr <= 2030 {
-                self.eyr = FieldState::Valid(eyr)
-            }
-        }
-    }
-
-    fn set_hgt(&mut self, val: &str) {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"^(\d{2,3})(\D{2})$").unwrap();
-        }
-        self.hgt = FieldState::Invalid;
-        if let Some(caps) = RE.captures(val) {
-            let num = caps.get(1).unwrap().as_str().parse::<u32>().unwrap(); // regex already validated number length
-            match caps.get(2).unwrap().as_str() {
-                "cm" => {
-                    if num >= 150 && num <= 193 { self.hgt = FieldState::Valid(Height::Centimetres(num)); }
-                },
-                "in" => {
-                    if num >= 59 && num <= 76 { self.hgt = FieldState::Valid(Height::Inches(num)); }
-                }
-                _ => ()
-            }
-        }
-    }
-
-    fn set_hcl(&mut self, val: &str) {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"^#([0-9a-f]{6})$").unwrap();
-        }
-        match RE.captures(val) {
-            Some(caps) => {
-                let bytes = hex::decode(caps.get(1).unwrap().as_str()).unwrap(); // regex already validated as hex string
-                self.hcl = FieldState::Valid(Colour { r: bytes[0], g: bytes[1], b: bytes[2] });    
-            },
-            None => self.hcl = FieldState::Invalid,
-        }
-    }
-
-    fn set_ecl(&mut self, val: &str) {
-        match EyeColour::from_str(val) {
-            Ok(colour) => self.ecl = FieldState::Valid(colour),
-            Err(_) => self.ecl = FieldState::Invalid,
-        }
-    }
-
-    fn set_pid(&mut self, val: &str) {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"^(\d{9})$").unwrap();
-        }
-        match RE.captures(val) {
-            Some(caps) => self.pid = FieldState::Valid(caps.get(1).unwrap().as_str().to_string()),
-            None => self.pid = FieldState::Invalid,
-        }
-    }
-
     fn all_fields_present(&self) -> bool {
-        self.byr.is_present() &&
-        self.iyr.is_present() &&
-        self.eyr.is_present() &&
-        self.hgt.is_present() &&
-        self.hcl.is_present() &&
-        self.ecl.is_present() &&
-        self.pid.is_present()
+        self.schema.fields.iter().zip(&self.fields).all(|(rule, field)| !rule.required || field.state.is_present())
     }
 
+    // Optional fields don't have to be there, but must be valid if they are.
     fn all_fields_valid(&self) -> bool {
-        self.byr.is_valid() &&
-        self.iyr.is_valid() &&
-        self.eyr.is_valid() &&
-        self.hgt.is_valid() &&
-        self.hcl.is_valid() &&
-        self.ecl.is_valid() &&
-        self.pid.is_valid()
-    }
-}
\ No newline at end of file
+        self.schema.fields.iter().zip(&self.fields).all(|(rule, field)| {
+            field.state.is_valid() || (!rule.required && !field.state.is_present())
+        })
+    }
+}
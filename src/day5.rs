@@ -1,21 +1,91 @@
+use std::collections::BTreeSet;
+use crate::utils;
+
+// The plane has 128 rows of 8 seats unless --rows=<n> and --columns=<n> say
+// otherwise.  Boarding passes have just enough F/B characters to pick a row and
+// L/R characters to pick a column, and anything else is rejected.
+//
+// Run with --map to see every seat in the plane - # for taken, . for free and
+// * for ours - followed by every gap in the seats that are taken.
+
 pub fn day5(input_lines: &[String]) -> (u64, u64) {
-    let mut passes: Vec<u64> = input_lines.iter().map(|line| decode_boarding_pass(line)).collect();
-    passes.sort_unstable();
-    let part1 = *passes.last().unwrap();
-    let part2 = passes.into_iter().fold(0, |current, new| {
-        if (new == (current + 1)) || (current == 0) {
-            new
-        } else {
-            current
+    let layout = Layout {
+        rows: utils::option_value("rows").map_or(128, |rows| rows.parse().expect("Invalid --rows")),
+        columns: utils::option_value("columns").map_or(8, |columns| columns.parse().expect("Invalid --columns")),
+    };
+    let mut taken = BTreeSet::new();
+    for (index, line) in input_lines.iter().enumerate() {
+        let seat = layout.decode_boarding_pass(line).unwrap_or_else(|error| panic!("Invalid input: boarding pass {} ({}) {}", index + 1, line, error));
+        if !taken.insert(seat) {
+            panic!("Invalid input: boarding pass {} ({}) is for seat {}, which is already taken", index + 1, line, seat);
+        }
+    }
+    let part1 = *taken.iter().next_back().expect("Invalid input: no boarding passes");
+
+    let gaps = gaps(&taken);
+    // Ours is the only free seat with taken seats either side.
+    let part2 = gaps.iter().find(|(first, last)| first == last).map(|&(seat, _)| seat).expect("Failed to solve part 2");
+
+    if utils::option_set("map") {
+        for row in 0..layout.rows {
+            let seats: String = (0..layout.columns).map(|column| {
+                let seat = row * layout.columns + column;
+                if seat == part2 { '*' } else if taken.contains(&seat) { '#' } else { '.' }
+            }).collect();
+            println!("{:>4} {}", row, seats);
+        }
+        println!("Gaps:");
+        for (first, last) in gaps {
+            if first == last {
+                println!("  seat {}", first);
+            } else {
+                println!("  seats {}-{} ({} seats)", first, last, last - first + 1);
+            }
         }
-    }) + 1;
+    }
+
     (part1,part2)
 }
 
-fn decode_boarding_pass(pass: &str) -> u64 {
-    let binary = pass.chars().map(|c| match c {
-        'B' | 'R' => '1',
-        _ => '0',
-    }).collect::<String>();
-    u64::from_str_radix(&binary, 2).unwrap()    
+// Every run of free seats between the first and last taken ones, as the first
+// and last seat in the run.
+fn gaps(taken: &BTreeSet<u64>) -> Vec<(u64, u64)> {
+    taken.iter().zip(taken.iter().skip(1)).filter(|&(&before, &after)| after > before + 1).map(|(&before, &after)| (before + 1, after - 1)).collect()
+}
+
+struct Layout {
+    rows: u64,
+    columns: u64,
+}
+
+impl Layout {
+    // How many characters it takes to pick one of `count` rows or columns.
+    fn bits_for(count: u64) -> usize {
+        (u64::BITS - count.saturating_sub(1).leading_zeros()) as usize
+    }
+
+    // Each half of the pass is a binary number, with B and R as the ones.
+    fn decode_boarding_pass(&self, pass: &str) -> Result<u64, String> {
+        let (row_bits, column_bits) = (Self::bits_for(self.rows), Self::bits_for(self.columns));
+        let pass: Vec<char> = pass.chars().collect();
+        if pass.len() != row_bits + column_bits {
+            return Err(format!("should be {} characters long, not {}", row_bits + column_bits, pass.len()));
+        }
+        let decode = |half: &[char], zero: char, one: char| {
+            half.iter().try_fold(0, |value, &c| match c {
+                _ if c == zero => Ok(value * 2),
+                _ if c == one => Ok(value * 2 + 1),
+                _ => Err(format!("has {} where it should have {} or {}", c, zero, one)),
+            })
+        };
+        let row = decode(&pass[..row_bits], 'F', 'B')?;
+        let column = decode(&pass[row_bits..], 'L', 'R')?;
+        if row >= self.rows {
+            return Err(format!("is for row {}, but there are only {} rows", row, self.rows));
+        }
+        if column >= self.columns {
+            return Err(format!("is for column {}, but there are only {} columns", column, self.columns));
+        }
+        Ok(row * self.columns + column)
+    }
 }
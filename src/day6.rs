@@ -1,26 +1,153 @@
-use std::collections::HashSet;
 use crate::utils;
 
+// Run with --stats to see, for every question, how many groups had anyone or
+// everyone answer it and how many people answered it altogether, along with
+// the most and least common answers.
+//
+// Run with --threshold=<k> to count the questions answered by at least k
+// members of each group, rather than anyone (part 1, k=1) or everyone (part 2).
+// k can also be "all", or a percentage of the group like 50%.
+
+const QUESTIONS: usize = 26;
+
 pub fn day6(input_lines: &[String]) -> (u64, u64) {
-    let groups = utils::group_lines_split_by_empty_line(input_lines);
-    let (unions, intersections): (Vec<HashSet<char>>, Vec<HashSet<char>>) = groups.iter().map(|group| group_responses(group)).unzip();
-    let part1 = unions.iter().map(|set| set.len()).sum::<usize>() as u64;
-    let part2 = intersections.iter().map(|set| set.len()).sum::<usize>() as u64;
+    let groups: Vec<Group> = utils::group_lines_split_by_empty_line(input_lines).iter().filter(|group| !group.is_empty()).map(|group| Group::parse(group)).collect();
+    let part1 = groups.iter().map(|group| group.answered_by_anyone().count()).sum::<u64>();
+    let part2 = groups.iter().map(|group| group.answered_by_everyone().count()).sum::<u64>();
+
+    if utils::option_set("stats") {
+        print_stats(&groups);
+    }
+    if let Some(threshold) = utils::option_value("threshold") {
+        let threshold = Threshold::parse(&threshold);
+        let total: u64 = groups.iter().map(|group| group.answered_by_at_least(threshold.members(group.members.len())).count()).sum();
+        println!("Questions answered by {} of each group, summed over the groups: {}", threshold, total);
+    }
+
     (part1,part2)
 }
 
-fn group_responses(group: &[String]) -> (HashSet<char>, HashSet<char>) {
-    let mut union: HashSet<char> = HashSet::new();
-    let mut intersection: HashSet<char> = HashSet::new();
-    union.extend(group[0].chars());
-    intersection.extend(group[0].chars());
-    for response in group[1..].iter() {
-        let mut new_set: HashSet<char> = HashSet::new();
-        for c in response.chars() {
-            union.insert(c);
-            new_set.insert(c);
+fn print_stats(groups: &[Group]) {
+    let mut anyone = [0u64; QUESTIONS];
+    let mut everyone = [0u64; QUESTIONS];
+    let mut people = [0u64; QUESTIONS];
+    for group in groups {
+        let (answered_by_anyone, answered_by_everyone, counts) = (group.answered_by_anyone(), group.answered_by_everyone(), group.counts());
+        for question in 0..QUESTIONS {
+            anyone[question] += answered_by_anyone.contains(question) as u64;
+            everyone[question] += answered_by_everyone.contains(question) as u64;
+            people[question] += counts[question] as u64;
         }
-        intersection.retain(|c| new_set.contains(c));
     }
-    (union, intersection)
-}
\ No newline at end of file
+    println!("Question  Groups (anyone)  Groups (everyone)  People");
+    for question in 0..QUESTIONS {
+        println!("{:>8}  {:>15}  {:>17}  {:>6}", question_name(question), anyone[question], everyone[question], people[question]);
+    }
+    // There may be ties for either, so list them all.  Questions nobody answered
+    // aren't answers at all, so they don't count as the least common.
+    let with_count = |count: u64| (0..QUESTIONS).filter(|&question| people[question] == count).map(question_name).collect::<String>();
+    if let (Some(&most), Some(&least)) = (people.iter().max(), people.iter().filter(|&&count| count > 0).min()) {
+        println!("Most common: {} ({} people)", with_count(most), most);
+        println!("Least common: {} ({} people)", with_count(least), least);
+    }
+}
+
+fn question_name(question: usize) -> char {
+    (b'a' + question as u8) as char
+}
+
+// Which questions were answered, one bit per question.
+#[derive(Clone, Copy)]
+struct Answers(u32);
+
+impl Answers {
+    const NONE: Answers = Answers(0);
+    const ALL: Answers = Answers((1 << QUESTIONS) - 1);
+
+    fn parse(input: &str) -> Self {
+        Answers(input.chars().fold(0, |bits, c| match c {
+            'a'..='z' => bits | 1 << (c as u32 - 'a' as u32),
+            _ => panic!("Invalid input: questions are a to z, not {}", c),
+        }))
+    }
+
+    fn contains(self, question: usize) -> bool {
+        self.0 & (1 << question) != 0
+    }
+
+    fn count(self) -> u64 {
+        self.0.count_ones() as u64
+    }
+}
+
+struct Group {
+    members: Vec<Answers>,
+}
+
+impl Group {
+    fn parse(input_lines: &[String]) -> Self {
+        Group { members: input_lines.iter().map(|line| Answers::parse(line)).collect() }
+    }
+
+    fn answered_by_anyone(&self) -> Answers {
+        self.members.iter().fold(Answers::NONE, |union, member| Answers(union.0 | member.0))
+    }
+
+    fn answered_by_everyone(&self) -> Answers {
+        self.members.iter().fold(Answers::ALL, |intersection, member| Answers(intersection.0 & member.0))
+    }
+
+    // How many members answered each question.
+    fn counts(&self) -> [usize; QUESTIONS] {
+        let mut counts = [0; QUESTIONS];
+        for member in &self.members {
+            for (question, count) in counts.iter_mut().enumerate() {
+                *count += member.contains(question) as usize;
+            }
+        }
+        counts
+    }
+
+    fn answered_by_at_least(&self, members: usize) -> Answers {
+        let counts = self.counts();
+        Answers((0..QUESTIONS).filter(|&question| counts[question] >= members).fold(0, |bits, question| bits | 1 << question))
+    }
+}
+
+enum Threshold {
+    Members(usize),
+    Everyone,
+    Percentage(usize),
+}
+
+impl Threshold {
+    fn parse(input: &str) -> Self {
+        if input == "all" {
+            Threshold::Everyone
+        } else if let Some(percentage) = input.strip_suffix('%') {
+            Threshold::Percentage(percentage.parse().expect("Invalid --threshold percentage"))
+        } else {
+            Threshold::Members(input.parse().expect("Invalid --threshold: expected a number, all or a percentage"))
+        }
+    }
+
+    // How many members of a group this size have to answer a question.  Nobody
+    // answering doesn't count as answering, so it's always at least one.
+    fn members(&self, group_size: usize) -> usize {
+        match self {
+            Threshold::Members(members) => (*members).max(1),
+            Threshold::Everyone => group_size.max(1),
+            Threshold::Percentage(percentage) => ((group_size * percentage).div_ceil(100)).max(1),
+        }
+    }
+}
+
+impl std::fmt::Display for Threshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Threshold::Members(members) => write!(f, "at least {}", members),
+            Threshold::Everyone => write!(f, "all"),
+            Threshold::Percentage(percentage) => write!(f, "at least {}%", percentage),
+        }
+    }
+}
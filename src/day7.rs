@@ -7,9 +7,9 @@ pub fn day7(input_lines: &[String]) -> (u64, u64) {
     let bags = parse_input(input_lines);
     let bag = bags.get("shiny gold").expect("No shiny gold bag in input!");
     let mut set: HashSet<&str> = HashSet::new();
-    add_bags_containing_bag(&mut set, &bag);
+    add_bags_containing_bag(&mut set, bag);
     let part1 = set.len() as u64;
-    let part2 = count_bag_contents(&bag);
+    let part2 = count_bag_contents(bag);
     (part1,part2)
 }
 
@@ -36,7 +36,7 @@ fn count_bag_contents(bag_cell: &RefCell<Bag>) -> u64 {
     count
 }
 
-fn parse_input(input_lines: &[String]) -> HashMap<&str, Rc<RefCell<Bag>>> {
+fn parse_input(input_lines: &[String]) -> HashMap<&str, Rc<RefCell<Bag<'_>>>> {
     let mut bags: HashMap<&str, Rc<RefCell<Bag>>> = HashMap::new();
 
     for line in input_lines {
@@ -26,7 +26,7 @@ enum ProgramResult {
 fn execute_program(instructions: &[String], change_line: Option<usize>) -> ProgramResult {
     let mut accumulator: u64 = 0;
     let mut instruction_ptr: usize = 0;
-    let mut visited_lines: Vec<bool> = iter::repeat(false).take(instructions.len()).collect();
+    let mut visited_lines: Vec<bool> = iter::repeat_n(false, instructions.len()).collect();
     while instruction_ptr < instructions.len() && !visited_lines[instruction_ptr] {
         visited_lines[instruction_ptr] = true;
         let flip = change_line == Some(instruction_ptr);
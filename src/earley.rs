@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+
+// A general context-free grammar engine using Earley's algorithm.  Unlike a
+// hand-rolled recursive matcher, this doesn't care what shape the grammar is:
+// left recursion, right recursion, ambiguity and empty alternatives all work.
+// Input is consumed a char at a time, so multi-byte UTF-8 is handled properly.
+//
+// Empty alternatives are handled using the Aycock-Horspool trick: when we
+// predict a rule that can match the empty string, we also immediately step
+// over it.
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Rule(usize),
+    Terminal(String),
+}
+
+pub enum ParseTree {
+    Rule(usize, Vec<ParseTree>),
+    Terminal(String),
+}
+
+impl std::fmt::Display for ParseTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseTree::Terminal(s) => write!(f, "\"{}\"", s),
+            ParseTree::Rule(id, children) => {
+                write!(f, "{}(", id)?;
+                for (index, child) in children.iter().enumerate() {
+                    if index > 0 { write!(f, " ")?; }
+                    write!(f, "{}", child)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Grammar {
+    rules: HashMap<usize, Vec<Vec<Symbol>>>,
+    nullable: HashSet<usize>,
+}
+
+impl Grammar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Define (or redefine) a rule as a list of alternatives, each of which is a
+    // sequence of symbols.  An empty sequence matches the empty string.
+    pub fn set_rule(&mut self, id: usize, alternatives: Vec<Vec<Symbol>>) {
+        self.rules.insert(id, alternatives);
+        self.nullable = self.find_nullable_rules();
+    }
+
+    // Does the specified rule match the whole of the input?
+    pub fn matches(&self, start: usize, input: &str) -> bool {
+        let input: Vec<char> = input.chars().collect();
+        let chart = self.build_chart(start, &input);
+        chart.completed(self, start, 0, input.len())
+    }
+
+    // As `matches`, but returns a parse tree if there's a match.  If the grammar
+    // is ambiguous, an arbitrary one of the possible trees is returned.
+    pub fn parse(&self, start: usize, input: &str) -> Option<ParseTree> {
+        let input: Vec<char> = input.chars().collect();
+        let chart = self.build_chart(start, &input);
+        let mut in_progress: HashSet<(usize, usize, usize)> = HashSet::new();
+        self.build_tree(&chart, &input, start, 0, input.len(), &mut in_progress)
+    }
+
+    // Can the rule match the empty string?
+    pub fn is_nullable(&self, rule: usize) -> bool {
+        self.nullable.contains(&rule)
+    }
+
+    fn alternatives(&self, rule: usize) -> &[Vec<Symbol>] {
+        // A rule that was never defined has no alternatives, so never matches.
+        self.rules.get(&rule).map_or(&[], Vec::as_slice)
+    }
+
+    fn symbol_after(&self, item: &Item) -> Option<&Symbol> {
+        self.alternatives(item.rule)[item.alternative].get(item.dot)
+    }
+
+    fn find_nullable_rules(&self) -> HashSet<usize> {
+        // Keep going round until we stop discovering new rules that can match
+        // the empty string.
+        let mut nullable: HashSet<usize> = HashSet::new();
+        loop {
+            let mut changed = false;
+            for (id, alternatives) in &self.rules {
+                if nullable.contains(id) { continue; }
+                let is_nullable = alternatives.iter().any(|alternative| alternative.iter().all(|symbol| match symbol {
+                    Symbol::Rule(subrule) => nullable.contains(subrule),
+                    Symbol::Terminal(s) => s.is_empty(),
+                }));
+                if is_nullable {
+                    nullable.insert(*id);
+                    changed = true;
+                }
+            }
+            if !changed { break; }
+        }
+        nullable
+    }
+
+    fn build_chart(&self, start: usize, input: &[char]) -> Chart {
+        let mut chart = Chart::new(input.len());
+        for alternative in 0..self.alternatives(start).len() {
+            chart.add(0, Item { rule: start, alternative, dot: 0, origin: 0 });
+        }
+
+        for position in 0..=input.len() {
+            // The set we're working on grows as we go, so iterate by index.
+            let mut index = 0;
+            while index < chart.sets[position].len() {
+                let item = chart.sets[position][index];
+                index += 1;
+                match self.symbol_after(&item) {
+                    None => {
+                        // Completion: advance everything that was waiting for
+                        // this rule to match.
+                        let waiting = chart.waiting[item.origin].get(&item.rule).cloned().unwrap_or_default();
+                        for parent in waiting {
+                            chart.add(position, parent.advance());
+                        }
+                    },
+                    Some(Symbol::Rule(subrule)) => {
+                        // Prediction.
+                        let subrule = *subrule;
+                        chart.waiting[position].entry(subrule).or_default().push(item);
+                        for alternative in 0..self.alternatives(subrule).len() {
+                            chart.add(position, Item { rule: subrule, alternative, dot: 0, origin: position });
+                        }
+                        if self.nullable.contains(&subrule) {
+                            chart.add(position, item.advance());
+                        }
+                    },
+                    Some(Symbol::Terminal(s)) => {
+                        // Scanning.
+                        if let Some(end) = terminal_end(s, input, position) {
+                            chart.add(end, item.advance());
+                        }
+                    },
+                }
+            }
+        }
+
+        chart
+    }
+
+    fn build_tree(&self, chart: &Chart, input: &[char], rule: usize, start: usize, end: usize, in_progress: &mut HashSet<(usize, usize, usize)>) -> Option<ParseTree> {
+        // Refuse to expand a rule over a span we're already in the middle of
+        // expanding - that only happens with cycles like "A: A", and there's
+        // always another derivation that avoids going round the cycle.
+        if !in_progress.insert((rule, start, end)) { return None; }
+
+        let mut tree = None;
+        for (alternative, symbols) in self.alternatives(rule).iter().enumerate() {
+            let item = Item { rule, alternative, dot: symbols.len(), origin: start };
+            if !chart.contains(end, &item) { continue; }
+            if let Some(children) = self.build_children(chart, input, item, end, in_progress) {
+                tree = Some(ParseTree::Rule(rule, children));
+                break;
+            }
+        }
+
+        in_progress.remove(&(rule, start, end));
+        tree
+    }
+
+    // Work backwards through an item's symbols, finding where each one started
+    // so that everything before the dot spans item.origin..end.
+    fn build_children(&self, chart: &Chart, input: &[char], item: Item, end: usize, in_progress: &mut HashSet<(usize, usize, usize)>) -> Option<Vec<ParseTree>> {
+        if item.dot == 0 {
+            return if item.origin == end { Some(Vec::new()) } else { None };
+        }
+
+        let previous = Item { dot: item.dot - 1, ..item };
+        match &self.alternatives(item.rule)[item.alternative][previous.dot] {
+            Symbol::Terminal(s) => {
+                let length = s.chars().count();
+                if end < item.origin + length { return None; }
+                let middle = end - length;
+                if terminal_end(s, input, middle) != Some(end) || !chart.contains(middle, &previous) { return None; }
+                let mut children = self.build_children(chart, input, previous, middle, in_progress)?;
+                children.push(ParseTree::Terminal(s.clone()));
+                Some(children)
+            },
+            Symbol::Rule(subrule) => {
+                for middle in (item.origin..=end).rev() {
+                    if !chart.contains(middle, &previous) || !chart.completed(self, *subrule, middle, end) { continue; }
+                    if let Some(subtree) = self.build_tree(chart, input, *subrule, middle, end, in_progress) {
+                        if let Some(mut children) = self.build_children(chart, input, previous, middle, in_progress) {
+                            children.push(subtree);
+                            return Some(children);
+                        }
+                    }
+                }
+                None
+            },
+        }
+    }
+}
+
+// If the terminal matches the input at the given position, where does the match end?
+fn terminal_end(terminal: &str, input: &[char], position: usize) -> Option<usize> {
+    let mut end = position;
+    for c in terminal.chars() {
+        if input.get(end) != Some(&c) { return None; }
+        end += 1;
+    }
+    Some(end)
+}
+
+// An Earley item: we're partway through matching one alternative of a rule,
+// having started at `origin` and got as far as `dot` symbols in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    alternative: usize,
+    dot: usize,
+    origin: usize,
+}
+
+impl Item {
+    fn advance(self) -> Self {
+        Self { dot: self.dot + 1, ..self }
+    }
+}
+
+struct Chart {
+    sets: Vec<Vec<Item>>,
+    seen: Vec<HashSet<Item>>,
+
+    // For each position, the items that are waiting on a rule starting there.
+    waiting: Vec<HashMap<usize, Vec<Item>>>,
+}
+
+impl Chart {
+    fn new(input_length: usize) -> Self {
+        Self {
+            sets: vec![Vec::new(); input_length + 1],
+            seen: vec![HashSet::new(); input_length + 1],
+            waiting: vec![HashMap::new(); input_length + 1],
+        }
+    }
+
+    fn add(&mut self, position: usize, item: Item) {
+        if self.seen[position].insert(item) {
+            self.sets[position].push(item);
+        }
+    }
+
+    fn contains(&self, position: usize, item: &Item) -> bool {
+        self.seen[position].contains(item)
+    }
+
+    // Has the specified rule been matched from start to end?
+    fn completed(&self, grammar: &Grammar, rule: usize, start: usize, end: usize) -> bool {
+        grammar.alternatives(rule).iter().enumerate().any(|(alternative, symbols)| {
+            self.contains(end, &Item { rule, alternative, dot: symbols.len(), origin: start })
+        })
+    }
+}
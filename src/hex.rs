@@ -0,0 +1,312 @@
+// Hexagonal grid maths, following the conventions at
+// https://www.redblobgames.com/grids/hexagons/.
+//
+// Axial coordinates (q, r) are the main representation - they're compact and
+// adding them just works.  Cube coordinates (q, r, s) with q + r + s = 0 make
+// distances, rounding and rotations easier to think about; doubled coordinates
+// give every hex a position on an ordinary rectangular grid, which is handy for
+// drawing (doubled width for pointy-top layouts, doubled height for flat-top).
+//
+// Direction strings name the neighbours of a hex without separators, eg.
+// "esenee".  Pointy-top grids have neighbours e, se, sw, w, nw and ne; flat-top
+// grids have n, ne, se, s, sw and nw.
+//
+// Day 24 only needs pointy-top axial coordinates, so the rest is marked as
+// allowed to be unused, item by item, for the other tools that need it.
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Axial {
+    pub q: i64,
+    pub r: i64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Cube {
+    pub q: i64,
+    pub r: i64,
+    pub s: i64,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Doubled {
+    pub col: i64,
+    pub row: i64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layout {
+    PointyTop,
+    #[allow(dead_code)]
+    FlatTop,
+}
+
+// The six neighbours, going clockwise.  The same offsets serve both layouts -
+// only their names differ.
+pub const NEIGHBOUR_OFFSETS: [Axial; 6] = [
+    Axial { q: 1, r: 0 },
+    Axial { q: 0, r: 1 },
+    Axial { q: -1, r: 1 },
+    Axial { q: -1, r: 0 },
+    Axial { q: 0, r: -1 },
+    Axial { q: 1, r: -1 },
+];
+
+impl Layout {
+    // Names of NEIGHBOUR_OFFSETS, in the same order.
+    pub fn direction_names(self) -> [&'static str; 6] {
+        match self {
+            Layout::PointyTop => ["e", "se", "sw", "w", "nw", "ne"],
+            Layout::FlatTop => ["se", "s", "sw", "nw", "n", "ne"],
+        }
+    }
+
+    pub fn direction(self, name: &str) -> Option<Axial> {
+        self.direction_names().iter().position(|&known| known == name).map(|index| NEIGHBOUR_OFFSETS[index])
+    }
+
+    // Split a string like "esenee" into the steps it describes.
+    pub fn parse_directions(self, input: &str) -> Result<Vec<Axial>, String> {
+        let mut directions = Vec::new();
+        let mut rest = input.trim();
+        while !rest.is_empty() {
+            // Two-letter names first, so "se" isn't read as "s" then "e".
+            let (direction, length) = [2, 1].iter()
+                .find_map(|&length| rest.get(..length).and_then(|name| self.direction(name)).map(|direction| (direction, length)))
+                .ok_or_else(|| format!("Invalid direction at \"{}\" in \"{}\"", rest, input))?;
+            directions.push(direction);
+            rest = &rest[length..];
+        }
+        Ok(directions)
+    }
+
+    #[allow(dead_code)]
+    pub fn doubled(self, hex: Axial) -> Doubled {
+        match self {
+            Layout::PointyTop => Doubled { col: 2 * hex.q + hex.r, row: hex.r },
+            Layout::FlatTop => Doubled { col: hex.q, row: 2 * hex.r + hex.q },
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn axial(self, doubled: Doubled) -> Axial {
+        assert!((doubled.col + doubled.row) % 2 == 0, "Doubled coordinates must have col + row even");
+        match self {
+            Layout::PointyTop => Axial { q: (doubled.col - doubled.row) / 2, r: doubled.row },
+            Layout::FlatTop => Axial { q: doubled.col, r: (doubled.row - doubled.col) / 2 },
+        }
+    }
+}
+
+impl Axial {
+    pub const ORIGIN: Axial = Axial { q: 0, r: 0 };
+
+    pub fn neighbours(self) -> impl Iterator<Item = Axial> {
+        NEIGHBOUR_OFFSETS.iter().map(move |&offset| self + offset)
+    }
+
+    #[allow(dead_code)]
+    pub fn distance(self, other: Axial) -> i64 {
+        Cube::from(self).distance(Cube::from(other))
+    }
+
+    // Every hex exactly `radius` steps away, clockwise from the one that's
+    // `radius` steps along NEIGHBOUR_OFFSETS[4].
+    #[allow(dead_code)]
+    pub fn ring(self, radius: i64) -> Vec<Axial> {
+        if radius == 0 {
+            return vec![self];
+        }
+        let mut ring = Vec::with_capacity(6 * radius as usize);
+        let mut hex = self + NEIGHBOUR_OFFSETS[4] * radius;
+        for &offset in &NEIGHBOUR_OFFSETS {
+            for _ in 0..radius {
+                ring.push(hex);
+                hex = hex + offset;
+            }
+        }
+        ring
+    }
+
+    // Every hex within `radius` steps, working outwards ring by ring.
+    #[allow(dead_code)]
+    pub fn spiral(self, radius: i64) -> Vec<Axial> {
+        (0..=radius).flat_map(|ring_radius| self.ring(ring_radius)).collect()
+    }
+
+    // The hexes on a straight line from here to `other`, both ends included.
+    #[allow(dead_code)]
+    pub fn line_to(self, other: Axial) -> Vec<Axial> {
+        let steps = self.distance(other);
+        if steps == 0 {
+            return vec![self];
+        }
+        let (start, end) = (Cube::from(self), Cube::from(other));
+        // Nudge the line slightly, so points exactly between two hexes always
+        // round the same way.
+        let lerp = |a: i64, b: i64, nudge: f64, t: f64| a as f64 + nudge + (b - a) as f64 * t;
+        (0..=steps).map(|step| {
+            let t = step as f64 / steps as f64;
+            Axial::from(Cube::round(lerp(start.q, end.q, 1e-6, t), lerp(start.r, end.r, 2e-6, t), lerp(start.s, end.s, -3e-6, t)))
+        }).collect()
+    }
+}
+
+impl Cube {
+    #[allow(dead_code)]
+    pub fn distance(self, other: Cube) -> i64 {
+        ((self.q - other.q).abs() + (self.r - other.r).abs() + (self.s - other.s).abs()) / 2
+    }
+
+    // The hex containing a fractional position.  Rounding each coordinate on its
+    // own can break q + r + s = 0, so fix up whichever moved furthest.
+    #[allow(dead_code)]
+    pub fn round(q: f64, r: f64, s: f64) -> Cube {
+        let (mut rounded_q, mut rounded_r, mut rounded_s) = (q.round(), r.round(), s.round());
+        let (q_diff, r_diff, s_diff) = ((rounded_q - q).abs(), (rounded_r - r).abs(), (rounded_s - s).abs());
+        if q_diff > r_diff && q_diff > s_diff {
+            rounded_q = -rounded_r - rounded_s;
+        } else if r_diff > s_diff {
+            rounded_r = -rounded_q - rounded_s;
+        } else {
+            rounded_s = -rounded_q - rounded_r;
+        }
+        Cube { q: rounded_q as i64, r: rounded_r as i64, s: rounded_s as i64 }
+    }
+}
+
+impl From<Axial> for Cube {
+    fn from(hex: Axial) -> Self {
+        Cube { q: hex.q, r: hex.r, s: -hex.q - hex.r }
+    }
+}
+
+impl From<Cube> for Axial {
+    fn from(cube: Cube) -> Self {
+        Axial { q: cube.q, r: cube.r }
+    }
+}
+
+impl std::ops::Add for Axial {
+    type Output = Axial;
+
+    fn add(self, rhs: Axial) -> Axial {
+        Axial { q: self.q + rhs.q, r: self.r + rhs.r }
+    }
+}
+
+impl std::ops::Sub for Axial {
+    type Output = Axial;
+
+    fn sub(self, rhs: Axial) -> Axial {
+        Axial { q: self.q - rhs.q, r: self.r - rhs.r }
+    }
+}
+
+impl std::ops::Mul<i64> for Axial {
+    type Output = Axial;
+
+    fn mul(self, rhs: i64) -> Axial {
+        Axial { q: self.q * rhs, r: self.r * rhs }
+    }
+}
+
+// One generation of a cellular automaton on a sparse set of live hexes.  Only
+// hexes that are live or next to a live hex are considered, so dead hexes need
+// at least one live neighbour to come alive.  `rule` is given whether a hex is
+// live and how many live neighbours it has, and says whether it's live next.
+pub fn step(live: &HashSet<Axial>, rule: impl Fn(bool, usize) -> bool) -> HashSet<Axial> {
+    let mut live_neighbours: HashMap<Axial, usize> = live.iter().map(|&hex| (hex, 0)).collect();
+    for hex in live {
+        for neighbour in hex.neighbours() {
+            *live_neighbours.entry(neighbour).or_insert(0) += 1;
+        }
+    }
+    live_neighbours.into_iter().filter(|&(hex, count)| rule(live.contains(&hex), count)).map(|(hex, _)| hex).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hexes_within(radius: i64) -> impl Iterator<Item = Axial> {
+        (-radius..=radius).flat_map(move |q| (-radius..=radius).map(move |r| Axial { q, r }))
+    }
+
+    #[test]
+    fn doubled_round_trips() {
+        for layout in [Layout::PointyTop, Layout::FlatTop] {
+            for hex in hexes_within(5) {
+                let doubled = layout.doubled(hex);
+                assert_eq!((doubled.col + doubled.row) % 2, 0);
+                assert_eq!(layout.axial(doubled), hex);
+            }
+            // Neighbours are next to each other on the doubled grid too.
+            for offset in NEIGHBOUR_OFFSETS {
+                let doubled = layout.doubled(offset);
+                assert!(doubled.col.abs() + doubled.row.abs() == 2, "{:?} {:?}", layout, offset);
+            }
+        }
+    }
+
+    #[test]
+    fn directions_in_both_layouts() {
+        let steps = Layout::PointyTop.parse_directions("esenee").unwrap();
+        assert_eq!(steps.into_iter().fold(Axial::ORIGIN, |hex, step| hex + step), Axial { q: 3, r: 0 });
+        let steps = Layout::FlatTop.parse_directions("nsesw").unwrap();
+        assert_eq!(steps.into_iter().fold(Axial::ORIGIN, |hex, step| hex + step), Axial::ORIGIN);
+        assert!(Layout::FlatTop.parse_directions("e").is_err());
+        assert!(Layout::PointyTop.parse_directions("n").is_err());
+    }
+
+    #[test]
+    fn rings_and_spirals() {
+        let centre = Axial { q: 2, r: -3 };
+        assert_eq!(centre.ring(0), vec![centre]);
+        for radius in 1..=6 {
+            let ring = centre.ring(radius);
+            assert_eq!(ring.len() as i64, 6 * radius);
+            assert!(ring.iter().all(|&hex| hex.distance(centre) == radius));
+            assert_eq!(ring.iter().collect::<HashSet<_>>().len(), ring.len());
+            // Each hex leads on to the next, all the way round.
+            for (index, &hex) in ring.iter().enumerate() {
+                assert_eq!(hex.distance(ring[(index + 1) % ring.len()]), 1);
+            }
+        }
+        for radius in 0..=6 {
+            let spiral = centre.spiral(radius);
+            assert_eq!(spiral.len() as i64, 3 * radius * (radius + 1) + 1);
+            let expected: HashSet<Axial> = hexes_within(radius).map(|offset| centre + offset).filter(|&hex| hex.distance(centre) <= radius).collect();
+            assert_eq!(spiral.into_iter().collect::<HashSet<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let start = Axial { q: -1, r: 2 };
+        assert_eq!(start.line_to(start), vec![start]);
+        for end in hexes_within(6) {
+            let line = start.line_to(end);
+            assert_eq!(line.len() as i64, start.distance(end) + 1);
+            assert_eq!(line.first(), Some(&start));
+            assert_eq!(line.last(), Some(&end));
+            assert!(line.windows(2).all(|pair| pair[0].distance(pair[1]) == 1), "{:?} -> {:?}: {:?}", start, end, line);
+        }
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(Cube::round(0.1, -0.2, 0.1), Cube { q: 0, r: 0, s: 0 });
+        // Rounding each on its own doesn't add up to 0, so whichever moved
+        // furthest has to give way.
+        assert_eq!(Cube::round(0.45, 0.4, -0.85), Cube { q: 1, r: 0, s: -1 });
+        assert_eq!(Cube::round(0.4, 0.45, -0.85), Cube { q: 0, r: 1, s: -1 });
+        assert_eq!(Cube::round(-0.85, 0.4, 0.45), Cube { q: -1, r: 0, s: 1 });
+        for hex in hexes_within(4) {
+            let cube = Cube::from(hex);
+            assert_eq!(Cube::round(cube.q as f64 + 0.2, cube.r as f64 - 0.1, cube.s as f64 - 0.1), cube);
+        }
+    }
+}
@@ -2,6 +2,10 @@
 #[macro_use] extern crate strum_macros;
 
 mod utils;
+mod earley;
+mod assignment;
+mod hex;
+mod modular;
 mod day1;
 mod day2;
 mod day3;
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+// Arithmetic modulo some number.  Products are worked out in 128 bits, so
+// nothing overflows on the way, for any modulus that fits in 64 bits - though
+// discrete_log needs to remember sqrt(modulus) values, so it's only practical
+// for moduli up to around 2^40.
+
+pub fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 * b as u128) % modulus as u128) as u64
+}
+
+// Square-and-multiply, so it takes O(log exponent) multiplications.
+pub fn pow_mod(base: u64, exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1 % modulus;
+    let mut base = base % modulus;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
+    }
+    result
+}
+
+// The x with a * x = 1, if there is one (ie. if a and the modulus are coprime).
+pub fn inverse_mod(a: u64, modulus: u64) -> Option<u64> {
+    // Extended Euclid, keeping track of the coefficient of a.
+    let (mut old_r, mut r) = (a as i128 % modulus as i128, modulus as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    if old_r != 1 { return None; }
+    Some(old_s.rem_euclid(modulus as i128) as u64)
+}
+
+// The smallest x with base^x = target, if there is one, by baby-step giant-step:
+// writing x = i * m + j with m = ceil(sqrt(modulus)), remember base^j for every
+// j < m, then step through target * base^(-m*i) until we hit one of them.  That
+// takes O(sqrt(modulus)) time and memory rather than O(modulus).
+//
+// The base has to be coprime to the modulus (so that it has an inverse).
+pub fn discrete_log(base: u64, target: u64, modulus: u64) -> Option<u64> {
+    let target = target % modulus;
+    // Worked out in integers - going via f64 can be out by one for big moduli.
+    let mut steps = modulus.isqrt();
+    if (steps as u128) * (steps as u128) < modulus as u128 {
+        steps += 1;
+    }
+
+    // Keep the smallest j for each value, so we find the smallest x.
+    let mut baby_steps: HashMap<u64, u64> = HashMap::with_capacity(steps as usize);
+    let mut value = 1 % modulus;
+    for j in 0..steps {
+        baby_steps.entry(value).or_insert(j);
+        value = mul_mod(value, base, modulus);
+    }
+
+    let giant_step = pow_mod(inverse_mod(base, modulus)?, steps, modulus);
+    let mut value = target;
+    for i in 0..steps {
+        if let Some(j) = baby_steps.get(&value) {
+            return Some(i * steps + j);
+        }
+        value = mul_mod(value, giant_step, modulus);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn powers() {
+        assert_eq!(pow_mod(7, 8, 20201227), 5764801);
+        assert_eq!(pow_mod(3, 0, 7), 1);
+        assert_eq!(pow_mod(3, 0, 1), 0);
+        // Fermat's little theorem, with a modulus that needs the 128-bit products.
+        let prime = 18446744073709551557;
+        assert_eq!(pow_mod(123456789, prime - 1, prime), 1);
+    }
+
+    #[test]
+    fn inverses() {
+        assert_eq!(inverse_mod(3, 7), Some(5));
+        assert_eq!(inverse_mod(10, 7), Some(5));
+        assert_eq!(inverse_mod(4, 6), None);
+        for a in 1..1000 {
+            assert_eq!(mul_mod(a, inverse_mod(a, 20201227).unwrap(), 20201227), 1);
+        }
+    }
+
+    #[test]
+    fn discrete_logs() {
+        // The example from the puzzle.
+        assert_eq!(discrete_log(7, 5764801, 20201227), Some(8));
+        assert_eq!(discrete_log(7, 17807724, 20201227), Some(11));
+        assert_eq!(discrete_log(7, 1, 20201227), Some(0));
+        // The smallest answer, when the base has a small order: 2 has order 3 mod 7.
+        assert_eq!(discrete_log(2, 4, 7), Some(2));
+        assert_eq!(discrete_log(2, 3, 7), None);
+        for exponent in [1, 1000, 123456, 20201225] {
+            assert_eq!(discrete_log(7, pow_mod(7, exponent, 20201227), 20201227), Some(exponent));
+        }
+    }
+}
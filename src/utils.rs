@@ -7,4 +7,31 @@ pub fn load_inputs(day: usize) -> Vec<String> {
 
 pub fn group_lines_split_by_empty_line(lines: &[String]) -> Vec<&[String]> {
     lines.split(|line| line.is_empty()).collect()
-}
\ No newline at end of file
+}
+
+// Anything on the command line after the day number is an option for that
+// day, given as either "--name" or "--name=value".
+pub fn option_set(name: &str) -> bool {
+    std::env::args().skip(2).any(|arg| match arg.strip_prefix("--") {
+        Some(option) => option == name || option.starts_with(&format!("{}=", name)),
+        None => false,
+    })
+}
+
+pub fn option_value(name: &str) -> Option<String> {
+    std::env::args().skip(2).find_map(|arg| {
+        let (option, value) = arg.strip_prefix("--")?.split_once('=')?;
+        if option == name { Some(value.to_string()) } else { None }
+    })
+}
+
+// Random numbers below a limit for tests, from a simple LCG so that their inputs
+// are the same every run.
+#[cfg(test)]
+pub fn random_numbers(seed: u64) -> impl FnMut(u64) -> u64 {
+    let mut seed = seed;
+    move |limit| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % limit
+    }
+}